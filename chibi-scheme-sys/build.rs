@@ -1,66 +1,473 @@
+#[cfg(feature = "bindgen")]
 extern crate bindgen;
+extern crate cc;
+extern crate pkg_config;
 
+#[cfg(feature = "bindgen")]
 use std::collections::HashSet;
 use std::env;
+use std::fs;
 use std::io;
-use std::path::Path;
-use std::process::Command;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::SystemTime;
+
+// The upstream release the bindings are written against. Every source, whether
+// it is the submodule, `CHIBI_SCHEME_SRC` or a fresh clone, must match it.
+const CHIBI_SCHEME_VERSION: &str = "0.9.1";
+const CHIBI_SCHEME_REPOSITORY: &str = "https://github.com/ashinn/chibi-scheme";
+
+// Cargo features of this crate, as they appear in `CARGO_FEATURE_*`, the
+// chibi-scheme compile-time option each one controls and whether it is on by
+// default. A disabled feature sets its option to 0 rather than leaving it to
+// `features.h`, except for options chibi-scheme leaves undefined by default,
+// which are only ever defined to 1.
+const FEATURES: &[(&str, &str, bool)] = &[
+    ("GREEN_THREADS", "SEXP_USE_GREEN_THREADS", true),
+    ("BIGNUMS", "SEXP_USE_BIGNUMS", true),
+    ("RATIOS", "SEXP_USE_RATIOS", true),
+    ("COMPLEX", "SEXP_USE_COMPLEX", true),
+    ("UTF8_STRINGS", "SEXP_USE_UTF8_STRINGS", true),
+    ("NATIVE_X86", "SEXP_USE_NATIVE_X86", false),
+    ("IMAGES", "SEXP_USE_IMAGE_LOADING", true),
+    ("WEAK_REFERENCES", "SEXP_USE_WEAK_REFERENCES", true),
+];
+
+// The sources of `libchibi-scheme` itself, as listed in chibi-scheme's Makefile.
+const CORE_SOURCES: &[&str] = &[
+    "gc.c",
+    "sexp.c",
+    "bignum.c",
+    "gc_heap.c",
+    "opcodes.c",
+    "vm.c",
+    "eval.c",
+    "simplify.c",
+];
+
+// Modules with C parts. These are built as shared objects next to their
+// `.sld`, or with the `static` feature compiled into `libchibi-scheme.a`,
+// since a static build cannot `dlopen` them. Entries missing from the sources
+// are skipped.
+const C_MODULES: &[&str] = &[
+    "lib/chibi/ast.sld",
+    "lib/chibi/disasm.sld",
+    "lib/chibi/filesystem.sld",
+    "lib/chibi/heap-stats.sld",
+    "lib/chibi/io.sld",
+    "lib/chibi/net.sld",
+    "lib/chibi/process.sld",
+    "lib/chibi/stty.sld",
+    "lib/chibi/system.sld",
+    "lib/chibi/time.sld",
+    "lib/chibi/weak.sld",
+    "lib/chibi/crypto/crypto.sld",
+    "lib/chibi/optimize/profile.sld",
+    "lib/chibi/optimize/rest.sld",
+    "lib/scheme/time.sld",
+    "lib/srfi/18.sld",
+    "lib/srfi/27.sld",
+    "lib/srfi/39.sld",
+    "lib/srfi/69.sld",
+    "lib/srfi/95.sld",
+    "lib/srfi/98.sld",
+    "lib/srfi/144.sld",
+    "lib/srfi/151.sld",
+];
 
 fn checkout(dir: &Path, tag: &str) -> io::Result<()> {
-    Command::new("git")
+    let status = Command::new("git")
         .current_dir(dir)
         .arg("clone")
-        .args(&["--depth", "100"])
-        .arg("--single-branch")
-        .arg("https://github.com/ashinn/chibi-scheme")
-        .status()?;
-
-    let status = Command::new("git")
-        .current_dir(dir.join("chibi-scheme"))
-        .arg("pull")
+        .args(&["--depth", "1"])
+        .args(&["--branch", tag])
+        .arg(CHIBI_SCHEME_REPOSITORY)
+        .arg("chibi-scheme-checkout")
         .status()?;
 
     if status.success() {
         Ok(())
     } else {
-        Err(io::Error::new(io::ErrorKind::Other, "Failed to checkout"))
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("git clone of {} at {} failed", CHIBI_SCHEME_REPOSITORY, tag),
+        ))
     }
 }
 
-fn make(dir: &Path) -> io::Result<()> {
-    Command::new("make")
-        .current_dir(dir.clone().join("chibi-scheme"))
-        .arg("clean")
-        .status()?;
+// Finds the chibi-scheme sources, in order of preference: an explicit
+// `CHIBI_SCHEME_SRC`, the `chibi-scheme` submodule, and, only if
+// `CHIBI_SCHEME_DOWNLOAD` is set, a clone of the pinned release.
+fn find_source(out_dir: &Path) -> Result<PathBuf, String> {
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_SRC");
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_DOWNLOAD");
 
-    Command::new("make")
-        .current_dir(dir.clone().join("chibi-scheme"))
-        .arg(format!("PREFIX={}/install", dir.clone().to_str().unwrap()))
-        .arg("uninstall")
-        .status()?;
+    if let Some(dir) = env::var_os("CHIBI_SCHEME_SRC") {
+        let dir = PathBuf::from(dir);
+        if !dir.join("Makefile").is_file() {
+            return Err(format!(
+                "CHIBI_SCHEME_SRC is set to {}, which does not contain the chibi-scheme sources",
+                dir.display()
+            ));
+        }
+        return Ok(dir);
+    }
 
-    Command::new("make")
-        .current_dir(dir.clone().join("chibi-scheme"))
-        .arg(format!("PREFIX={}/install", dir.clone().to_str().unwrap()))
-        .status()?;
+    let submodule = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("chibi-scheme");
+    if submodule.join("Makefile").is_file() {
+        return Ok(submodule);
+    }
 
-    let status = Command::new("make")
-        .current_dir(dir.clone().join("chibi-scheme"))
-        .arg(format!("PREFIX={}/install", dir.clone().to_str().unwrap()))
-        .arg("install")
-        .status()?;
+    if env::var_os("CHIBI_SCHEME_DOWNLOAD").is_none() {
+        return Err(format!(
+            "chibi-scheme sources not found at {}; run `git submodule update --init`, \
+             point CHIBI_SCHEME_SRC at a chibi-scheme {} checkout, or set \
+             CHIBI_SCHEME_DOWNLOAD=1 to clone it from {}",
+            submodule.display(),
+            CHIBI_SCHEME_VERSION,
+            CHIBI_SCHEME_REPOSITORY
+        ));
+    }
+
+    let clone = out_dir.join("chibi-scheme-checkout");
+    if !clone.join("Makefile").is_file() {
+        checkout(out_dir, CHIBI_SCHEME_VERSION).map_err(|e| {
+            format!("{}; run `git submodule update --init` or set CHIBI_SCHEME_SRC instead", e)
+        })?;
+    }
+    Ok(clone)
+}
+
+// Refuses to build against anything but the pinned release, since the
+// hand-written accessors in `lib.rs` depend on its object layout.
+fn check_version(dir: &Path) -> Result<(), String> {
+    let version = fs::read_to_string(dir.join("VERSION"))
+        .map_err(|e| format!("could not read {}: {}", dir.join("VERSION").display(), e))?;
+    if version.trim() == CHIBI_SCHEME_VERSION {
+        Ok(())
+    } else {
+        Err(format!(
+            "chibi-scheme sources at {} are version {}, but {} is required",
+            dir.display(),
+            version.trim(),
+            CHIBI_SCHEME_VERSION
+        ))
+    }
+}
+
+// Everything is built in a copy of the sources, since the generated files
+// (`install.h`, the C output of chibi-ffi, `clibs.c`) and the shared objects of
+// the modules go next to the files they come from. Only files that changed are
+// copied again, so that unchanged objects stay up to date.
+fn sync_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            sync_dir(&entry.path(), &target)?;
+        } else if !is_newer(&target, &entry.path())? {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn modified(path: &Path) -> io::Result<SystemTime> {
+    fs::metadata(path)?.modified()
+}
+
+// Whether `path` exists and was modified after `than`.
+fn is_newer(path: &Path, than: &Path) -> io::Result<bool> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.modified()? > modified(than)?),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+// The most recent modification under `dir`, which every object depends on
+// when `dir` holds headers.
+fn newest(dir: &Path) -> io::Result<SystemTime> {
+    let mut latest = modified(dir)?;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let time = if path.is_dir() {
+            newest(&path)?
+        } else {
+            modified(&path)?
+        };
+        latest = latest.max(time);
+    }
+    Ok(latest)
+}
 
+// Leaves the file, and so its modification time, alone if it already holds
+// `contents`.
+fn write_if_changed(path: &Path, contents: &str) -> io::Result<()> {
+    if fs::read_to_string(path).ok().as_ref().map(|s| s.as_str()) != Some(contents) {
+        fs::write(path, contents)?;
+    }
+    Ok(())
+}
+
+fn run(command: &mut Command, what: &str) -> io::Result<()> {
+    let status = command.status()?;
     if status.success() {
         Ok(())
     } else {
-        Err(io::Error::new(io::ErrorKind::Other, "Failed to run make"))
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} failed with {}: {:?}", what, status, command),
+        ))
+    }
+}
+
+// What the Makefile would write to `include/chibi/install.h`. The default
+// module path points into the build directory, where the modules' shared
+// objects are too.
+fn install_h(build_dir: &Path) -> io::Result<String> {
+    let read = |name: &str| -> io::Result<String> {
+        Ok(fs::read_to_string(build_dir.join(name))?.trim().to_string())
+    };
+    let platform = match env::var("CARGO_CFG_TARGET_OS").unwrap().as_str() {
+        "macos" | "ios" => "macosx",
+        "windows" => "windows",
+        "freebsd" | "openbsd" | "netbsd" | "dragonfly" => "bsd",
+        "android" => "android",
+        "linux" => "linux",
+        _ => "unix",
+    };
+    Ok(format!(
+        "#define sexp_so_extension \"{}\"\n\
+         #define sexp_default_module_path \"{}\"\n\
+         #define sexp_platform \"{}\"\n\
+         #define sexp_architecture \"{}\"\n\
+         #define sexp_version \"{}\"\n\
+         #define sexp_release_name \"{}\"\n",
+        module_extension(),
+        build_dir.join("lib").display(),
+        platform,
+        env::var("CARGO_CFG_TARGET_ARCH").unwrap(),
+        read("VERSION")?,
+        read("RELEASE")?
+    ))
+}
+
+fn module_extension() -> &'static str {
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() == "windows" {
+        ".dll"
+    } else {
+        ".so"
+    }
+}
+
+// Compiles each source to an object in `obj_dir`, skipping sources whose
+// object is newer than both the source and `headers`. A different compiler or
+// different flags throw away everything compiled so far.
+fn compile(
+    build: &cc::Build,
+    sources: &[PathBuf],
+    obj_dir: &Path,
+    headers: SystemTime,
+) -> io::Result<Vec<PathBuf>> {
+    let compiler = build.get_compiler();
+    let signature = format!("{:?} {:?}", compiler.path(), compiler.args());
+    let stamp = obj_dir.join("flags");
+    if fs::read_to_string(&stamp).ok().as_ref() != Some(&signature) {
+        if obj_dir.exists() {
+            fs::remove_dir_all(obj_dir)?;
+        }
+        fs::create_dir_all(obj_dir)?;
+        fs::write(&stamp, &signature)?;
+    }
+
+    let mut objects = vec![];
+    for source in sources {
+        let name = source.file_stem().unwrap().to_str().unwrap();
+        let object = obj_dir.join(format!("{}.o", name));
+        let fresh = is_newer(&object, source)? && modified(&object)? > headers;
+        if !fresh {
+            let mut command = compiler.to_command();
+            if compiler.is_like_msvc() {
+                command.arg(format!("/Fo{}", object.display()));
+            } else {
+                command.arg("-o").arg(&object);
+            }
+            run(
+                command.arg("-c").arg(source),
+                &format!("compiling {}", source.display()),
+            )?;
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+// Links `objects` into `output` unless it is newer than all of them.
+fn link(compiler: &cc::Tool, objects: &[PathBuf], output: &Path, args: &[&str]) -> io::Result<()> {
+    let mut fresh = output.exists();
+    for object in objects {
+        fresh = fresh && is_newer(output, object)?;
+    }
+    if fresh {
+        return Ok(());
+    }
+    run(
+        compiler
+            .to_command()
+            .args(objects)
+            .arg("-o")
+            .arg(output)
+            .args(args),
+        &format!("linking {}", output.display()),
+    )
+}
+
+fn configure(build: &mut cc::Build, build_dir: &Path, defines: &[String]) {
+    build.include(build_dir.join("include")).warnings(false);
+    for define in defines {
+        let mut define = define.splitn(2, '=');
+        build.define(define.next().unwrap(), define.next());
+    }
+}
+
+// A chibi-scheme interpreter for the machine running the build, which the
+// build needs to turn `.stub` files into C and to generate `clibs.c`. It has
+// no C modules of its own, which neither tool uses.
+fn host_chibi(build_dir: &Path, out_dir: &Path, headers: SystemTime) -> io::Result<PathBuf> {
+    let host = env::var("HOST").unwrap();
+    let mut build = cc::Build::new();
+    build
+        .host(&host)
+        .target(&host)
+        .opt_level(2)
+        .debug(false)
+        .cargo_metadata(false);
+    configure(&mut build, build_dir, &default_defines());
+    build.define("SEXP_USE_DL", "0");
+
+    let mut sources: Vec<PathBuf> = CORE_SOURCES.iter().map(|s| build_dir.join(s)).collect();
+    sources.push(build_dir.join("main.c"));
+    let objects = compile(&build, &sources, &out_dir.join("host"), headers)?;
+    let chibi = out_dir.join("host").join("chibi-scheme");
+    link(&build.get_compiler(), &objects, &chibi, &["-lm"])?;
+    Ok(chibi)
+}
+
+// The C modules present in the sources, as paths relative to the build
+// directory without an extension, taken from the `include-shared` forms of
+// their library definitions.
+fn c_modules(build_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut modules = vec![];
+    for sld in C_MODULES.iter().map(|lib| build_dir.join(lib)) {
+        if !sld.is_file() {
+            continue;
+        }
+        let definition = fs::read_to_string(&sld)?;
+        for part in definition.split("(include-shared \"").skip(1) {
+            let name = &part[..part.find('"').unwrap_or(0)];
+            modules.push(sld.parent().unwrap().join(name));
+        }
+    }
+    Ok(modules
+        .into_iter()
+        .map(|module| module.strip_prefix(build_dir).unwrap().to_path_buf())
+        .collect())
+}
+
+// Runs chibi-ffi over every `.stub` module whose C file is out of date.
+fn ffi(chibi: &Path, build_dir: &Path, modules: &[PathBuf]) -> io::Result<()> {
+    let tool = build_dir.join("tools").join("chibi-ffi");
+    for module in modules {
+        let stub = build_dir.join(module).with_extension("stub");
+        let c = build_dir.join(module).with_extension("c");
+        if !stub.is_file() || (is_newer(&c, &stub)? && is_newer(&c, &tool)?) {
+            continue;
+        }
+        run(
+            Command::new(chibi)
+                .current_dir(build_dir)
+                .env("CHIBI_MODULE_PATH", "lib")
+                .args(&["-q", "tools/chibi-ffi"])
+                .arg(&stub)
+                .arg(&c),
+            &format!("chibi-ffi {}", stub.display()),
+        )?;
+    }
+    Ok(())
+}
+
+// Generates `clibs.c`, which `#include`s the C file of every module and lists
+// their entry points for `SEXP_USE_STATIC_LIBS`.
+fn genstatic(chibi: &Path, build_dir: &Path) -> io::Result<PathBuf> {
+    let libs: Vec<&str> = C_MODULES
+        .iter()
+        .cloned()
+        .filter(|lib| build_dir.join(lib).is_file())
+        .collect();
+    let clibs = build_dir.join("clibs.c");
+
+    let mut genstatic = Command::new(chibi)
+        .current_dir(build_dir)
+        .env("CHIBI_MODULE_PATH", "lib")
+        .args(&["-q", "tools/chibi-genstatic"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    genstatic
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(libs.join("\n").as_bytes())?;
+    let output = genstatic.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "Failed to generate clibs.c",
+        ));
     }
+    write_if_changed(&clibs, &String::from_utf8_lossy(&output.stdout))?;
+    Ok(clibs)
+}
+
+fn defines(enabled: impl Fn(&str, bool) -> bool) -> Vec<String> {
+    FEATURES
+        .iter()
+        .filter_map(|&(feature, option, default)| {
+            if enabled(feature, default) {
+                Some(format!("{}=1", option))
+            } else if default {
+                Some(format!("{}=0", option))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn feature_defines() -> Vec<String> {
+    defines(|feature, _| env::var_os(format!("CARGO_FEATURE_{}", feature)).is_some())
+}
+
+fn default_defines() -> Vec<String> {
+    defines(|_, default| default)
+}
+
+fn static_defines() -> Vec<String> {
+    vec!["SEXP_USE_DL=0".into(), "SEXP_USE_STATIC_LIBS=1".into()]
 }
 
 // Taken from https://github.com/rust-lang/rust-bindgen/issues/687
+#[cfg(feature = "bindgen")]
 #[derive(Debug)]
 struct IgnoreMacros(HashSet<String>);
 
+#[cfg(feature = "bindgen")]
 impl bindgen::callbacks::ParseCallbacks for IgnoreMacros {
     fn will_parse_macro(&self, name: &str) -> bindgen::callbacks::MacroParsingBehavior {
         if self.0.contains(name) {
@@ -71,10 +478,346 @@ impl bindgen::callbacks::ParseCallbacks for IgnoreMacros {
     }
 }
 
-fn main() {
-    let out_dir = env::var("OUT_DIR").unwrap();
-    checkout(Path::new(&out_dir), "0.8").unwrap();
-    make(Path::new(&out_dir)).unwrap();
+// Where the headers for bindgen live, once chibi-scheme has been built or
+// found, the preprocessor definitions the library was compiled with and the
+// root of its Scheme module tree.
+struct Library {
+    include_dir: PathBuf,
+    defines: Vec<String>,
+    module_dir: PathBuf,
+}
+
+// Builds chibi-scheme with the compiler cargo is configured for, so `CC`,
+// `CFLAGS`, `TARGET`, `OPT_LEVEL` and `DEBUG` (and through them the profile)
+// apply as they do to any other C dependency. Objects are kept between builds
+// and only recompiled when their source or a header changes.
+fn build_from_source(out_dir: &str, statik: bool) -> Library {
+    let out_dir = Path::new(out_dir);
+    let source = find_source(out_dir)
+        .and_then(|source| check_version(&source).map(|_| source))
+        .unwrap_or_else(|e| panic!("{}", e));
+    rerun_if_source_changed(&source);
+
+    let build_dir = out_dir.join("chibi-scheme");
+    sync_dir(&source, &build_dir).unwrap();
+    write_if_changed(
+        &build_dir.join("include").join("chibi").join("install.h"),
+        &install_h(&build_dir).unwrap(),
+    )
+    .unwrap();
+    let headers = newest(&build_dir.join("include")).unwrap();
+
+    let mut defines = feature_defines();
+    if statik {
+        defines.extend(static_defines());
+    }
+    let chibi = host_chibi(&build_dir, out_dir, headers).unwrap();
+    let modules = c_modules(&build_dir).unwrap();
+    ffi(&chibi, &build_dir, &modules).unwrap();
+
+    let mut build = cc::Build::new();
+    build.cargo_metadata(false).out_dir(&build_dir);
+    configure(&mut build, &build_dir, &defines);
+    let mut sources: Vec<PathBuf> = CORE_SOURCES.iter().map(|s| build_dir.join(s)).collect();
+    println!("cargo:rustc-link-search=native={}", build_dir.display());
+    if statik {
+        let clibs = genstatic(&chibi, &build_dir).unwrap();
+        sources.push(clibs);
+        let objects = compile(&build, &sources, &out_dir.join("obj"), headers).unwrap();
+        let mut archive = build.clone();
+        for object in objects {
+            archive.object(object);
+        }
+        archive.compile("chibi-scheme");
+        link_static_dependencies();
+    } else {
+        build_shared(&build, &build_dir, out_dir, &sources, &modules, headers).unwrap();
+        println!("cargo:rustc-link-lib=chibi-scheme");
+    }
+    Library {
+        include_dir: build_dir.join("include"),
+        defines: defines,
+        module_dir: build_dir.join("lib"),
+    }
+}
+
+fn rerun_if_source_changed(source: &Path) {
+    // `lib` covers the Scheme side of the modules as well as their C and stub
+    // files.
+    let inputs = ["include", "lib", "tools", "main.c", "VERSION", "RELEASE"];
+    for input in inputs.iter().chain(CORE_SOURCES) {
+        println!("cargo:rerun-if-changed={}", source.join(input).display());
+    }
+}
+
+// `libchibi-scheme` as a shared object, and each C module as a shared object
+// next to its `.sld` for `dlopen`, linked against it.
+fn build_shared(
+    build: &cc::Build,
+    build_dir: &Path,
+    out_dir: &Path,
+    sources: &[PathBuf],
+    modules: &[PathBuf],
+    headers: SystemTime,
+) -> io::Result<()> {
+    let compiler = build.get_compiler();
+    let apple = env::var("CARGO_CFG_TARGET_VENDOR").unwrap() == "apple";
+    let (library, shared) = if apple {
+        ("libchibi-scheme.dylib", "-dynamiclib")
+    } else {
+        ("libchibi-scheme.so", "-shared")
+    };
+    let objects = compile(build, sources, &out_dir.join("obj"), headers)?;
+    let args = [shared, "-lm", "-ldl"];
+    link(&compiler, &objects, &build_dir.join(library), &args)?;
+
+    let search = format!("-L{}", build_dir.display());
+    for module in modules {
+        let c = build_dir.join(module).with_extension("c");
+        if !c.is_file() {
+            continue;
+        }
+        let mut module_build = build.clone();
+        module_build.include(c.parent().unwrap());
+        let obj_dir = out_dir.join("obj").join(module);
+        let objects = compile(&module_build, &[c], &obj_dir, headers)?;
+        let output = build_dir
+            .join(module)
+            .with_extension(&module_extension()[1..]);
+        link(
+            &compiler,
+            &objects,
+            &output,
+            &["-shared", &search, "-lchibi-scheme"],
+        )?;
+    }
+    Ok(())
+}
+
+// The system libraries `libchibi-scheme.a` and its C modules need, which a
+// shared object would otherwise have recorded itself.
+fn link_static_dependencies() {
+    println!("cargo:rustc-link-lib=static=chibi-scheme");
+    println!("cargo:rustc-link-lib=m");
+    if env::var("CARGO_CFG_TARGET_OS").unwrap() == "linux" {
+        println!("cargo:rustc-link-lib=util");
+    }
+}
+
+// Links an already installed chibi-scheme. Explicit directories win over
+// pkg-config, which is only consulted for whatever was not given. The
+// installed headers already describe how the library was configured, so the
+// cargo features are not passed on and have to match it.
+fn find_system(statik: bool) -> Library {
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_INCLUDE_DIR");
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_MODULE_DIR");
+    let lib_dir = env::var_os("CHIBI_SCHEME_LIB_DIR").map(PathBuf::from);
+    let include_dir = env::var_os("CHIBI_SCHEME_INCLUDE_DIR").map(PathBuf::from);
+
+    let include_dir = match lib_dir {
+        Some(lib_dir) => {
+            println!("cargo:rustc-link-search=native={}", lib_dir.display());
+            if statik {
+                link_static_dependencies();
+            } else {
+                println!("cargo:rustc-link-lib=chibi-scheme");
+            }
+            include_dir.unwrap_or_else(|| lib_dir.join("..").join("include"))
+        }
+        None => {
+            let library = pkg_config::Config::new()
+                .exactly_version(CHIBI_SCHEME_VERSION)
+                .statik(statik)
+                .probe("chibi-scheme")
+                .unwrap_or_else(|e| {
+                    panic!(
+                        "could not find chibi-scheme {} with pkg-config ({}); \
+                         set CHIBI_SCHEME_LIB_DIR and CHIBI_SCHEME_INCLUDE_DIR instead",
+                        CHIBI_SCHEME_VERSION, e
+                    )
+                });
+            include_dir
+                .or_else(|| library.include_paths.into_iter().next())
+                .unwrap_or_else(|| PathBuf::from("/usr/include"))
+        }
+    };
+
+    if !include_dir.join("chibi").join("eval.h").is_file() {
+        panic!(
+            "chibi/eval.h not found under {}; set CHIBI_SCHEME_INCLUDE_DIR",
+            include_dir.display()
+        );
+    }
+    check_installed_version(&include_dir);
+    let module_dir = env::var_os("CHIBI_SCHEME_MODULE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| include_dir.join("..").join("share").join("chibi"));
+    Library {
+        include_dir: include_dir,
+        defines: vec![],
+        module_dir: module_dir,
+    }
+}
+
+// Installed headers record their release in `install.h`. Explicit paths skip
+// pkg-config's version check, so at least warn when they point elsewhere.
+fn check_installed_version(include_dir: &Path) {
+    let install_h = fs::read_to_string(include_dir.join("chibi").join("install.h"))
+        .unwrap_or_default();
+    let expected = format!("\"{}\"", CHIBI_SCHEME_VERSION);
+    let version = install_h
+        .lines()
+        .find(|line| line.starts_with("#define sexp_version "));
+    if let Some(line) = version {
+        if !line.contains(&expected) {
+            println!(
+                "cargo:warning=system chibi-scheme is not version {} ({})",
+                CHIBI_SCHEME_VERSION, line
+            );
+        }
+    }
+}
+
+fn collect_lib(root: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_lib(root, &path, files)?;
+        } else if path
+            .extension()
+            .map_or(false, |ext| ext == "scm" || ext == "sld")
+        {
+            let name = path
+                .strip_prefix(root)
+                .unwrap()
+                .components()
+                .map(|c| c.as_os_str().to_str().unwrap())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((name, path));
+        }
+    }
+    Ok(())
+}
+
+// Generates `embedded_lib.rs`, which `include_bytes!`s every `.scm` and `.sld`
+// file of the module tree, for the `embed-lib` feature.
+fn embed_lib(module_dir: &Path, out_dir: &str) -> io::Result<()> {
+    if !module_dir.join("init-7.scm").is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "no chibi-scheme module tree at {}; set CHIBI_SCHEME_MODULE_DIR",
+                module_dir.display()
+            ),
+        ));
+    }
+    let mut files = vec![];
+    collect_lib(module_dir, module_dir, &mut files)?;
+    files.sort();
+
+    let mut out = fs::File::create(Path::new(out_dir).join("embedded_lib.rs"))?;
+    writeln!(
+        out,
+        "/// The `.scm` and `.sld` files of the module tree, by path relative to its root."
+    )?;
+    writeln!(out, "pub static EMBEDDED_LIB: &[(&str, &[u8])] = &[")?;
+    for (name, path) in files {
+        writeln!(out, "    ({:?}, include_bytes!({:?})),", name, path)?;
+    }
+    writeln!(out, "];")
+}
+
+// Without `bindgen`, the bindings come from `src/bindings`, where they are
+// kept for the default options, with and without `static`, so that building
+// does not need libclang. Installed libraries are assumed to use the defaults.
+fn pregenerated_bindings(library: &Library) -> Option<PathBuf> {
+    let target = env::var("TARGET").unwrap();
+    let dir = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("src/bindings");
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut defaults = default_defines();
+    if library.defines.is_empty() || library.defines == defaults {
+        return Some(dir.join(format!("{}.rs", target)));
+    }
+    defaults.extend(static_defines());
+    if library.defines == defaults {
+        Some(dir.join(format!("{}-static.rs", target)))
+    } else {
+        None
+    }
+}
+
+// The target triple as clang spells it, where that differs from rustc.
+#[cfg(feature = "bindgen")]
+fn clang_target(target: &str) -> String {
+    let mut parts = target.splitn(2, '-');
+    let arch = parts.next().unwrap();
+    let rest = parts.next().unwrap_or("");
+    let arch = if arch.starts_with("riscv64") {
+        "riscv64"
+    } else if arch.starts_with("riscv32") {
+        "riscv32"
+    } else {
+        arch
+    };
+    format!("{}-{}", arch, rest)
+}
+
+// Asks the C compiler for the target where its system headers are, so that
+// bindgen parses the same ones the library was compiled against: first its
+// sysroot, which `CHIBI_SCHEME_SYSROOT` overrides, then the directories it
+// searches for `<...>` includes. Only needed when cross-compiling; clang finds
+// the host's headers by itself.
+#[cfg(feature = "bindgen")]
+fn target_clang_args() -> Vec<String> {
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_SYSROOT");
+    let target = env::var("TARGET").unwrap();
+    let mut args = vec![format!("--target={}", clang_target(&target))];
+    if target == env::var("HOST").unwrap() {
+        return args;
+    }
+
+    let compiler = cc::Build::new().cargo_metadata(false).get_compiler();
+    let sysroot = env::var("CHIBI_SCHEME_SYSROOT").ok().or_else(|| {
+        let output = compiler.to_command().arg("-print-sysroot").output().ok()?;
+        let sysroot = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !sysroot.is_empty() {
+            Some(sysroot)
+        } else {
+            None
+        }
+    });
+    if let Some(sysroot) = sysroot {
+        args.push(format!("--sysroot={}", sysroot));
+    }
+
+    // `cc -E -v` lists the include path between these two lines on stderr.
+    let output = compiler
+        .to_command()
+        .args(&["-E", "-v", "-x", "c", "-"])
+        .stdin(Stdio::null())
+        .output();
+    if let Ok(output) = output {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let dirs = stderr
+            .lines()
+            .skip_while(|line| !line.starts_with("#include <...> search starts here:"))
+            .skip(1)
+            .take_while(|line| !line.starts_with("End of search list."));
+        for dir in dirs {
+            args.push(format!("-isystem{}", dir.trim()));
+        }
+    }
+    args
+}
+
+// Runs bindgen over `eval.h`. With `CHIBI_SCHEME_UPDATE_BINDINGS` set the
+// result also replaces the pregenerated bindings, which is how they are
+// refreshed and how CI checks that they are current.
+#[cfg(feature = "bindgen")]
+fn generate_bindings(library: &Library, out_dir: &str, pregenerated: Option<&Path>) {
     let ignored_macros = IgnoreMacros(
         vec![
             "FP_INFINITE".into(),
@@ -91,9 +834,18 @@ fn main() {
         ].into_iter()
         .collect(),
     );
-    let bindings = bindgen::Builder::default()
-        .header(format!("{}/chibi-scheme/include/chibi/eval.h", &out_dir))
-        .clang_arg(format!("-I/{}/chibi-scheme/include", &out_dir))
+    let bindings = library
+        .defines
+        .iter()
+        .fold(bindgen::Builder::default(), |builder, define| {
+            builder.clang_arg(format!("-D{}", define))
+        })
+        .header(format!(
+            "{}",
+            library.include_dir.join("chibi").join("eval.h").display()
+        ))
+        .clang_arg(format!("-I{}", library.include_dir.display()))
+        .clang_args(target_clang_args())
         .parse_callbacks(Box::new(ignored_macros))
         .generate()
         .expect("Unable to generate bindings");
@@ -101,6 +853,320 @@ fn main() {
         .write_to_file(format!("{}/bindings.rs", out_dir))
         .expect("Could not write bindings");
 
-    println!("cargo:rustc-link-search=native={}/chibi-scheme/", &out_dir);
-    println!("cargo:rustc-link-lib=chibi-scheme");
+    println!("cargo:rerun-if-env-changed=CHIBI_SCHEME_UPDATE_BINDINGS");
+    if env::var_os("CHIBI_SCHEME_UPDATE_BINDINGS").is_some() {
+        let pregenerated = pregenerated.unwrap_or_else(|| {
+            panic!("bindings are only pregenerated for the default features, with and without `static`")
+        });
+        fs::create_dir_all(pregenerated.parent().unwrap()).unwrap();
+        fs::copy(format!("{}/bindings.rs", out_dir), pregenerated).unwrap();
+    }
+}
+
+// The sexp.h macros that lib.rs ports to Rust, as (name, argument types,
+// return type) in their Rust spelling. Each one is compiled into a C function
+// `sexp_shim_<name>` so the ports can be checked against, or replaced by, what
+// the headers actually say for this chibi-scheme and these SEXP_USE_* options.
+const SHIMS: &[(&str, &[&str], &str)] = &[
+    ("sexp_unbox_fixnum", &["sexp"], "sexp_sint_t"),
+    ("sexp_flonum_value", &["sexp"], "f64"),
+    ("sexp_make_character", &["raw::c_int"], "sexp"),
+    ("sexp_unbox_character", &["sexp"], "raw::c_int"),
+    ("sexp_make_boolean", &["bool"], "sexp"),
+    ("sexp_unbox_boolean", &["sexp"], "bool"),
+    ("sexp_truep", &["sexp"], "bool"),
+    ("sexp_not", &["sexp"], "bool"),
+    ("sexp_nullp", &["sexp"], "bool"),
+    ("sexp_eofp", &["sexp"], "bool"),
+    ("sexp_fixnump", &["sexp"], "bool"),
+    ("sexp_flonump", &["sexp"], "bool"),
+    ("sexp_exceptionp", &["sexp"], "bool"),
+    ("sexp_exception_message", &["sexp"], "sexp"),
+    ("sexp_isymbolp", &["sexp"], "bool"),
+    ("sexp_lsymbolp", &["sexp"], "bool"),
+    ("sexp_symbolp", &["sexp"], "bool"),
+    ("sexp_charp", &["sexp"], "bool"),
+    ("sexp_exact_integerp", &["sexp"], "bool"),
+    ("sexp_integerp", &["sexp"], "bool"),
+    ("sexp_bignump", &["sexp"], "bool"),
+    ("sexp_ratiop", &["sexp"], "bool"),
+    ("sexp_complexp", &["sexp"], "bool"),
+    ("sexp_realp", &["sexp"], "bool"),
+    ("sexp_numberp", &["sexp"], "bool"),
+    ("sexp_booleanp", &["sexp"], "bool"),
+    ("sexp_pointerp", &["sexp"], "bool"),
+    ("sexp_pointer_tag", &["sexp"], "sexp_tag_t"),
+    ("sexp_check_tag", &["sexp", "sexp_tag_t"], "bool"),
+    ("sexp_stringp", &["sexp"], "bool"),
+    ("sexp_envp", &["sexp"], "bool"),
+    ("sexp_pairp", &["sexp"], "bool"),
+    ("sexp_car", &["sexp"], "sexp"),
+    ("sexp_cdr", &["sexp"], "sexp"),
+    ("sexp_pair_source", &["sexp"], "sexp"),
+    ("sexp_string_size", &["sexp"], "sexp_uint_t"),
+    ("sexp_bytes_length", &["sexp"], "sexp_uint_t"),
+    ("sexp_bytes_data", &["sexp"], "*mut raw::c_char"),
+    ("sexp_string_offset", &["sexp"], "sexp_uint_t"),
+    ("sexp_string_bytes", &["sexp"], "sexp"),
+    ("sexp_string_data", &["sexp"], "*mut raw::c_char"),
+    ("sexp_string_length", &["sexp"], "sexp_uint_t"),
+    ("sexp_lsymbol_data", &["sexp"], "*mut raw::c_char"),
+    ("sexp_lsymbol_length", &["sexp"], "sexp_uint_t"),
+    ("sexp_vectorp", &["sexp"], "bool"),
+    ("sexp_vector_length", &["sexp"], "sexp_uint_t"),
+    ("sexp_vector_data", &["sexp"], "*mut sexp"),
+    ("sexp_vector_ref", &["sexp", "sexp"], "sexp"),
+    ("sexp_vector_set", &["sexp", "sexp", "sexp"], "()"),
+    ("sexp_bytesp", &["sexp"], "bool"),
+    ("sexp_bytes_ref", &["sexp", "sexp"], "sexp"),
+    ("sexp_bytes_set", &["sexp", "sexp", "sexp"], "()"),
+    ("sexp_bignum_sign", &["sexp"], "raw::c_schar"),
+    ("sexp_bignum_length", &["sexp"], "sexp_uint_t"),
+    ("sexp_bignum_data", &["sexp"], "*mut sexp_uint_t"),
+    ("sexp_ratio_numerator", &["sexp"], "sexp"),
+    ("sexp_ratio_denominator", &["sexp"], "sexp"),
+    ("sexp_complex_real", &["sexp"], "sexp"),
+    ("sexp_complex_imag", &["sexp"], "sexp"),
+    ("sexp_procedurep", &["sexp"], "bool"),
+    ("sexp_opcodep", &["sexp"], "bool"),
+    ("sexp_applicablep", &["sexp"], "bool"),
+    ("sexp_procedure_flags", &["sexp"], "raw::c_char"),
+    ("sexp_procedure_num_args", &["sexp"], "sexp_proc_num_args_t"),
+    ("sexp_procedure_code", &["sexp"], "sexp"),
+    ("sexp_procedure_vars", &["sexp"], "sexp"),
+    ("sexp_procedure_variadic_p", &["sexp"], "bool"),
+    ("sexp_procedure_unused_rest_p", &["sexp"], "bool"),
+    ("sexp_procedure_variable_transformer_p", &["sexp"], "bool"),
+    ("sexp_bytecode_name", &["sexp"], "sexp"),
+    ("sexp_bytecode_source", &["sexp"], "sexp"),
+    ("sexp_procedure_source", &["sexp"], "sexp"),
+    ("sexp_opcode_class", &["sexp"], "raw::c_uchar"),
+    ("sexp_opcode_code", &["sexp"], "raw::c_uchar"),
+    ("sexp_opcode_num_args", &["sexp"], "raw::c_uchar"),
+    ("sexp_opcode_flags", &["sexp"], "raw::c_uchar"),
+    ("sexp_opcode_inverse", &["sexp"], "raw::c_uchar"),
+    ("sexp_opcode_name", &["sexp"], "sexp"),
+    ("sexp_opcode_data", &["sexp"], "sexp"),
+    ("sexp_opcode_data2", &["sexp"], "sexp"),
+    ("sexp_opcode_proc", &["sexp"], "sexp"),
+    ("sexp_opcode_return_type", &["sexp"], "sexp"),
+    ("sexp_opcode_arg1_type", &["sexp"], "sexp"),
+    ("sexp_opcode_arg2_type", &["sexp"], "sexp"),
+    ("sexp_opcode_arg3_type", &["sexp"], "sexp"),
+    ("sexp_opcode_argn_type", &["sexp"], "sexp"),
+    ("sexp_opcode_methods", &["sexp"], "sexp"),
+    ("sexp_opcode_func", &["sexp"], "sexp_proc1"),
+    ("sexp_opcode_variadic_p", &["sexp"], "bool"),
+    ("sexp_opcode_opt_param_p", &["sexp"], "bool"),
+    ("sexp_opcode_ref_trans_p", &["sexp"], "bool"),
+    ("sexp_opcode_static_param_p", &["sexp"], "bool"),
+    ("sexp_opcode_tail_call_p", &["sexp"], "bool"),
+    ("sexp_iportp", &["sexp"], "bool"),
+    ("sexp_oportp", &["sexp"], "bool"),
+    ("sexp_portp", &["sexp"], "bool"),
+    ("sexp_port_stream", &["sexp"], "*mut FILE"),
+    ("sexp_port_name", &["sexp"], "sexp"),
+    ("sexp_port_line", &["sexp"], "sexp_uint_t"),
+    ("sexp_port_openp", &["sexp"], "bool"),
+    ("sexp_port_binaryp", &["sexp"], "bool"),
+    ("sexp_port_cookie", &["sexp"], "sexp"),
+    ("sexp_port_buf", &["sexp"], "*mut raw::c_char"),
+    ("sexp_port_size", &["sexp"], "sexp_uint_t"),
+    ("sexp_port_offset", &["sexp"], "sexp_uint_t"),
+    ("sexp_exception_kind", &["sexp"], "sexp"),
+    ("sexp_exception_irritants", &["sexp"], "sexp"),
+    ("sexp_exception_procedure", &["sexp"], "sexp"),
+    ("sexp_exception_source", &["sexp"], "sexp"),
+    ("sexp_typep", &["sexp"], "bool"),
+    ("sexp_type_tag", &["sexp"], "sexp_tag_t"),
+    ("sexp_type_field_base", &["sexp"], "raw::c_short"),
+    ("sexp_type_field_len_base", &["sexp"], "raw::c_short"),
+    ("sexp_type_name", &["sexp"], "sexp"),
+    ("sexp_type_cpl", &["sexp"], "sexp"),
+    ("sexp_type_slots", &["sexp"], "sexp"),
+    ("sexp_type_getters", &["sexp"], "sexp"),
+    ("sexp_type_setters", &["sexp"], "sexp"),
+    ("sexp_context_globals", &["sexp"], "sexp"),
+    ("sexp_context_env", &["sexp"], "sexp"),
+    ("sexp_context_saves", &["sexp"], "*mut sexp_gc_var_t"),
+    ("sexp_global", &["sexp", "sexp_context_globals"], "sexp"),
+    ("sexp_context_types", &["sexp"], "*mut sexp"),
+    ("sexp_type_by_index", &["sexp", "sexp_tag_t"], "sexp"),
+    ("sexp_object_type", &["sexp", "sexp"], "sexp"),
+    ("sexp_object_type_name", &["sexp", "sexp"], "sexp"),
+    ("sexp_slot_ref", &["sexp", "sexp_uint_t"], "sexp"),
+    ("sexp_slot_set", &["sexp", "sexp_uint_t", "sexp"], "()"),
+    ("sexp_cpointerp", &["sexp"], "bool"),
+    ("sexp_cpointer_value", &["sexp"], "*mut raw::c_void"),
+    (
+        "sexp_cpointer_maybe_null_value",
+        &["sexp"],
+        "*mut raw::c_void",
+    ),
+    ("sexp_cpointer_length", &["sexp"], "sexp_uint_t"),
+    ("sexp_cpointer_parent", &["sexp"], "sexp"),
+    ("sexp_promisep", &["sexp"], "bool"),
+    ("sexp_promise_donep", &["sexp"], "bool"),
+    ("sexp_promise_value", &["sexp"], "sexp"),
+    ("sexp_env_parent", &["sexp"], "sexp"),
+    ("sexp_env_bindings", &["sexp"], "sexp"),
+    ("sexp_env_lambda", &["sexp"], "sexp"),
+    ("sexp_env_next_cell", &["sexp"], "sexp"),
+];
+
+// The C spelling of a type in SHIMS. Flags and enums cross as plain ints.
+fn c_type(ty: &str) -> &str {
+    match ty {
+        "()" => "void",
+        "bool" | "sexp_context_globals" | "raw::c_int" => "int",
+        "f64" => "double",
+        "raw::c_char" => "char",
+        "raw::c_schar" => "signed char",
+        "raw::c_uchar" => "unsigned char",
+        "raw::c_short" => "short",
+        "*mut raw::c_char" => "char*",
+        "*mut raw::c_void" => "void*",
+        "*mut sexp" => "sexp*",
+        "*mut sexp_uint_t" => "sexp_uint_t*",
+        "*mut FILE" => "FILE*",
+        "*mut sexp_gc_var_t" => "struct sexp_gc_var_t*",
+        ty => ty,
+    }
+}
+
+// The type the extern declaration of a shim uses for `ty`.
+fn ffi_type(ty: &str) -> &str {
+    match c_type(ty) {
+        "int" => "raw::c_int",
+        _ => ty,
+    }
+}
+
+fn shim_source() -> (String, String) {
+    let mut c = String::from("#include <chibi/eval.h>\n");
+    let mut rust = String::from("extern \"C\" {\n");
+    let mut wrappers = String::new();
+    for &(name, args, ret) in SHIMS {
+        let shim = name.replacen("sexp_", "sexp_shim_", 1);
+        let params: Vec<String> = (0..args.len()).map(|i| format!("a{}", i)).collect();
+
+        let c_params: Vec<String> = args
+            .iter()
+            .zip(&params)
+            .map(|(ty, p)| format!("{} {}", c_type(ty), p))
+            .collect();
+        let call = format!("{}({})", name, params.join(", "));
+        let body = match ret {
+            "()" => format!("{};", call),
+            "bool" => format!("return {} ? 1 : 0;", call),
+            _ => format!("return {};", call),
+        };
+        c.push_str(&format!(
+            "\n{} {}({}) {{\n  {}\n}}\n",
+            c_type(ret),
+            shim,
+            c_params.join(", "),
+            body
+        ));
+
+        let ffi_params: Vec<String> = args
+            .iter()
+            .zip(&params)
+            .map(|(ty, p)| format!("{}: {}", p, ffi_type(ty)))
+            .collect();
+        let ffi_ret = match ret {
+            "()" => String::new(),
+            ret => format!(" -> {}", ffi_type(ret)),
+        };
+        rust.push_str(&format!(
+            "    fn {}({}){};\n",
+            shim,
+            ffi_params.join(", "),
+            ffi_ret
+        ));
+
+        let rust_params: Vec<String> = args
+            .iter()
+            .zip(&params)
+            .map(|(ty, p)| format!("{}: {}", p, ty))
+            .collect();
+        let rust_args: Vec<String> = args
+            .iter()
+            .zip(&params)
+            .map(|(ty, p)| match ffi_type(ty) {
+                ffi if ffi != *ty => format!("{} as {}", p, ffi),
+                _ => p.clone(),
+            })
+            .collect();
+        let rust_call = format!("{}({})", shim, rust_args.join(", "));
+        let (rust_ret, rust_body) = match ret {
+            "()" => (String::new(), format!("unsafe {{ {} }}", rust_call)),
+            "bool" => (
+                " -> bool".into(),
+                format!("unsafe {{ {} != 0 }}", rust_call),
+            ),
+            ret => (
+                format!(" -> {}", ret),
+                format!("unsafe {{ {} }}", rust_call),
+            ),
+        };
+        wrappers.push_str(&format!(
+            "\npub fn {}({}){} {{\n    {}\n}}\n",
+            name,
+            rust_params.join(", "),
+            rust_ret,
+            rust_body
+        ));
+    }
+    rust.push_str("}\n");
+    rust.push_str(&wrappers);
+    (c, rust)
+}
+
+// Writes shim.c and the matching shim.rs into OUT_DIR and compiles the former.
+fn shim(library: &Library, out_dir: &str) -> io::Result<()> {
+    let (c, rust) = shim_source();
+    let c_file = Path::new(out_dir).join("shim.c");
+    fs::write(&c_file, c)?;
+    fs::write(Path::new(out_dir).join("shim.rs"), rust)?;
+
+    let mut build = cc::Build::new();
+    build.file(&c_file).include(&library.include_dir);
+    for define in &library.defines {
+        let mut define = define.splitn(2, '=');
+        build.define(define.next().unwrap(), define.next());
+    }
+    build.compile("chibi-scheme-shim");
+    Ok(())
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let system = env::var_os("CARGO_FEATURE_SYSTEM").is_some()
+        || env::var_os("CHIBI_SCHEME_LIB_DIR").is_some();
+    let statik = env::var_os("CARGO_FEATURE_STATIC").is_some();
+    let library = if system {
+        find_system(statik)
+    } else {
+        build_from_source(&out_dir, statik)
+    };
+    if env::var_os("CARGO_FEATURE_EMBED_LIB").is_some() {
+        embed_lib(&library.module_dir, &out_dir).unwrap_or_else(|e| panic!("{}", e));
+    }
+    shim(&library, &out_dir).unwrap();
+    let bindings = pregenerated_bindings(&library);
+    #[cfg(feature = "bindgen")]
+    generate_bindings(&library, &out_dir, bindings.as_ref().map(|p| p.as_path()));
+    #[cfg(not(feature = "bindgen"))]
+    {
+        let bindings = bindings.filter(|p| p.is_file()).unwrap_or_else(|| {
+            panic!(
+                "no pregenerated bindings for {} with these features; \
+                 enable the `bindgen` feature to generate them (needs libclang)",
+                env::var("TARGET").unwrap()
+            )
+        });
+        fs::copy(&bindings, format!("{}/bindings.rs", out_dir)).unwrap();
+    }
 }
@@ -4,11 +4,29 @@
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+#[cfg(feature = "embed-lib")]
+include!(concat!(env!("OUT_DIR"), "/embedded_lib.rs"));
+
 use std::mem;
 
 use std::os::raw;
 use std::ptr;
 
+// The sexp.h macros, either ported to Rust or, with the `shim` feature, called
+// through C functions compiled from the headers themselves.
+#[cfg_attr(feature = "shim", allow(dead_code))]
+mod macros;
+#[cfg_attr(not(feature = "shim"), allow(dead_code))]
+mod shim {
+    use super::*;
+    include!(concat!(env!("OUT_DIR"), "/shim.rs"));
+}
+
+#[cfg(not(feature = "shim"))]
+pub use macros::*;
+#[cfg(feature = "shim")]
+pub use shim::*;
+
 const fn sexp_make_immediate(n: u32) -> sexp {
     ((n << SEXP_EXTENDED_BITS) + SEXP_EXTENDED_TAG) as sexp
 }
@@ -17,161 +35,666 @@ pub const fn sexp_make_fixnum(n: i64) -> sexp {
     (((n as sexp_sint_t) << SEXP_FIXNUM_BITS) + (SEXP_FIXNUM_TAG as sexp_sint_t)) as sexp
 }
 
-pub fn sexp_unbox_fixnum(n: sexp) -> sexp_sint_t {
-    (n as sexp_sint_t) >> SEXP_FIXNUM_BITS
-}
-
-pub fn sexp_flonum_value(x: sexp) -> f64 {
-    unsafe { *(*x).value.flonum.as_ref() }
-}
-
-pub fn sexp_make_character(n: raw::c_char) -> sexp {
-    (((n as sexp_sint_t) << SEXP_EXTENDED_BITS) + (SEXP_CHAR_TAG as sexp_sint_t)) as sexp
-}
-
-pub fn sexp_unbox_character(n: sexp) -> raw::c_char {
-    ((n as sexp_sint_t) >> SEXP_EXTENDED_BITS) as raw::c_char
-}
-
 pub const SEXP_FALSE: sexp = sexp_make_immediate(0);
 pub const SEXP_TRUE: sexp = sexp_make_immediate(1);
 pub const SEXP_NULL: sexp = sexp_make_immediate(2);
 pub const SEXP_EOF: sexp = sexp_make_immediate(3);
 pub const SEXP_VOID: sexp = sexp_make_immediate(4);
+pub const SEXP_UNDEF: sexp = sexp_make_immediate(5);
 
 pub const SEXP_MAX_FIXNUM: sexp_sint_t =
     (1 << (mem::size_of::<sexp_sint_t>() as u32 * 8 - SEXP_FIXNUM_BITS - 1) as sexp_sint_t) - 1;
 pub const SEXP_MIN_FIXNUM: sexp_sint_t = -SEXP_MAX_FIXNUM - 1;
 pub const SEXP_SEVEN: sexp = sexp_make_fixnum(7);
-pub fn sexp_truep(x: sexp) -> bool {
-    x != SEXP_FALSE
-}
 
-pub fn sexp_not(x: sexp) -> bool {
-    x == SEXP_FALSE
+pub fn sexp_cons(ctx: sexp, a: sexp, b: sexp) -> sexp {
+    unsafe { sexp_cons_op(ctx, ptr::null_mut(), 2, a, b) }
 }
 
-pub fn sexp_nullp(x: sexp) -> bool {
-    x == SEXP_NULL
+pub fn sexp_listp(ctx: sexp, x: sexp) -> sexp {
+    unsafe { sexp_listp_op(ctx, ptr::null_mut(), 1, x) }
 }
 
-pub fn sexp_fixnump(x: sexp) -> bool {
-    ((x as sexp_uint_t) & SEXP_FIXNUM_MASK as sexp_uint_t) == SEXP_FIXNUM_TAG as sexp_uint_t
+pub fn sexp_equalp(ctx: sexp, a: sexp, b: sexp) -> sexp {
+    unsafe { sexp_equalp_op(ctx, ptr::null_mut(), 2, a, b) }
 }
 
-pub fn sexp_flonump(x: sexp) -> bool {
-    sexp_check_tag(x, sexp_types_SEXP_FLONUM)
+pub fn sexp_symbol_to_string(ctx: sexp, s: sexp) -> sexp {
+    unsafe { sexp_symbol_to_string_op(ctx, ptr::null_mut(), 1, s) }
 }
 
-pub fn sexp_exceptionp(x: sexp) -> bool {
-    sexp_check_tag(x, sexp_types_SEXP_EXCEPTION)
+pub fn sexp_string_to_symbol(ctx: sexp, s: sexp) -> sexp {
+    unsafe { sexp_string_to_symbol_op(ctx, ptr::null_mut(), 1, s) }
 }
 
-pub fn sexp_exception_message(x: sexp) -> sexp {
-    unsafe { (*x).value.exception.as_ref().message }
+pub fn sexp_make_string(ctx: sexp, len: sexp, ch: sexp) -> sexp {
+    unsafe { sexp_make_string_op(ctx, ptr::null_mut(), 2, len, ch) }
 }
 
-pub fn sexp_isymbolp(x: sexp) -> bool {
-    ((x as sexp_uint_t) & SEXP_IMMEDIATE_MASK as sexp_uint_t) == SEXP_ISYMBOL_TAG as sexp_uint_t
+pub fn sexp_string_to_number(ctx: sexp, s: sexp, base: sexp) -> sexp {
+    unsafe { sexp_string_to_number_op(ctx, ptr::null_mut(), 2, s, base) }
 }
 
-pub fn sexp_lsymbolp(x: sexp) -> bool {
-    sexp_check_tag(x, sexp_types_SEXP_SYMBOL)
+pub fn sexp_list1(ctx: sexp, a: sexp) -> sexp {
+    sexp_cons(ctx, a, SEXP_NULL)
 }
 
-pub fn sexp_symbolp(x: sexp) -> bool {
-    sexp_isymbolp(x) || sexp_lsymbolp(x)
+pub fn sexp_caar(x: sexp) -> sexp {
+    sexp_car(sexp_car(x))
 }
 
-pub fn sexp_charp(x: sexp) -> bool {
-    ((x as sexp_uint_t) & SEXP_EXTENDED_MASK as sexp_uint_t) == SEXP_CHAR_TAG as sexp_uint_t
+pub fn sexp_cadr(x: sexp) -> sexp {
+    sexp_car(sexp_cdr(x))
 }
 
-pub fn sexp_integerp(x: sexp) -> bool {
-    ((x as sexp_uint_t) & SEXP_FIXNUM_MASK as sexp_uint_t) == SEXP_FIXNUM_TAG as sexp_uint_t
+pub fn sexp_cdar(x: sexp) -> sexp {
+    sexp_cdr(sexp_car(x))
 }
 
-pub fn sexp_realp(x: sexp) -> bool {
-    sexp_integerp(x) || sexp_flonump(x)
+pub fn sexp_cddr(x: sexp) -> sexp {
+    sexp_cdr(sexp_cdr(x))
 }
 
-pub fn sexp_booleanp(x: sexp) -> bool {
-    x == SEXP_TRUE || x == SEXP_FALSE
+pub fn sexp_caddr(x: sexp) -> sexp {
+    sexp_car(sexp_cddr(x))
 }
 
-pub fn sexp_pointerp(x: sexp) -> bool {
-    ((x as sexp_uint_t) & SEXP_POINTER_MASK as sexp_uint_t) == SEXP_POINTER_TAG as sexp_uint_t
+pub fn sexp_cdddr(x: sexp) -> sexp {
+    sexp_cdr(sexp_cddr(x))
 }
 
-pub fn sexp_pointer_tag(x: sexp) -> sexp_tag_t {
-    unsafe { (*x).tag }
+pub fn sexp_cadddr(x: sexp) -> sexp {
+    sexp_cadr(sexp_cddr(x))
 }
 
-pub fn sexp_check_tag(x: sexp, t: sexp_tag_t) -> bool {
-    sexp_pointerp(x) && (sexp_pointer_tag(x) == t)
+pub fn sexp_length(ctx: sexp, ls: sexp) -> sexp {
+    unsafe { sexp_length_op(ctx, ptr::null_mut(), 1, ls) }
 }
 
-pub fn sexp_stringp(x: sexp) -> bool {
-    sexp_check_tag(x, sexp_types_SEXP_STRING)
+pub fn sexp_reverse(ctx: sexp, ls: sexp) -> sexp {
+    unsafe { sexp_reverse_op(ctx, ptr::null_mut(), 1, ls) }
 }
 
-pub fn sexp_envp(x: sexp) -> bool {
-    sexp_check_tag(x, sexp_types_SEXP_ENV)
+pub fn sexp_append2(ctx: sexp, a: sexp, b: sexp) -> sexp {
+    unsafe { sexp_append2_op(ctx, ptr::null_mut(), 2, a, b) }
 }
 
-pub fn sexp_pairp(x: sexp) -> bool {
-    sexp_check_tag(x, sexp_types_SEXP_PAIR)
+pub fn sexp_memq(ctx: sexp, x: sexp, ls: sexp) -> sexp {
+    unsafe { sexp_memq_op(ctx, ptr::null_mut(), 2, x, ls) }
 }
 
-pub fn sexp_cons(ctx: sexp, a: sexp, b: sexp) -> sexp {
-    unsafe { sexp_cons_op(ctx, ptr::null_mut(), 2, a, b) }
+pub fn sexp_assq(ctx: sexp, x: sexp, ls: sexp) -> sexp {
+    unsafe { sexp_assq_op(ctx, ptr::null_mut(), 2, x, ls) }
 }
 
-pub fn sexp_listp(ctx: sexp, x: sexp) -> sexp {
-    unsafe { sexp_listp_op(ctx, ptr::null_mut(), 1, x) }
+pub fn sexp_list_to_vector(ctx: sexp, ls: sexp) -> sexp {
+    unsafe { sexp_list_to_vector_op(ctx, ptr::null_mut(), 1, ls) }
 }
 
-pub fn sexp_car(x: sexp) -> sexp {
-    unsafe { (*x).value.pair.as_ref().car }
+pub fn sexp_make_vector(ctx: sexp, len: sexp, dflt: sexp) -> sexp {
+    unsafe { sexp_make_vector_op(ctx, ptr::null_mut(), 2, len, dflt) }
 }
 
-pub fn sexp_cdr(x: sexp) -> sexp {
-    unsafe { (*x).value.pair.as_ref().cdr }
+pub fn sexp_make_bytes(ctx: sexp, len: sexp, i: sexp) -> sexp {
+    unsafe { sexp_make_bytes_op(ctx, ptr::null_mut(), 2, len, i) }
 }
 
-pub fn sexp_string_size(x: sexp) -> sexp_uint_t {
-    unsafe { (*x).value.string.as_ref().length }
+#[cfg(not(feature = "bignums"))]
+pub fn sexp_make_integer(_ctx: sexp, x: i64) -> sexp {
+    sexp_make_fixnum(x)
 }
 
-pub fn sexp_bytes_length(x: sexp) -> sexp_uint_t {
-    unsafe { (*x).value.bytes.as_ref().length }
+pub fn sexp_define_foreign(
+    ctx: sexp,
+    env: sexp,
+    name: *const raw::c_char,
+    num_args: raw::c_int,
+    f: sexp_proc1,
+) -> sexp {
+    unsafe { sexp_define_foreign_aux(ctx, env, name, num_args, 0, name, f, ptr::null_mut()) }
 }
 
-pub fn sexp_bytes_data(x: sexp) -> *const raw::c_char {
-    unsafe { (*x).value.bytes.as_ref().data.as_ptr() }
+pub fn sexp_define_foreign_opt(
+    ctx: sexp,
+    env: sexp,
+    name: *const raw::c_char,
+    num_args: raw::c_int,
+    f: sexp_proc1,
+    dflt: sexp,
+) -> sexp {
+    unsafe { sexp_define_foreign_aux(ctx, env, name, num_args, 1, name, f, dflt) }
 }
 
-pub fn sexp_string_offset(x: sexp) -> sexp_uint_t {
-    unsafe { (*x).value.string.as_ref().offset }
+pub fn sexp_define_foreign_proc(
+    ctx: sexp,
+    env: sexp,
+    name: *const raw::c_char,
+    num_args: raw::c_int,
+    f: sexp_proc1,
+) -> sexp {
+    unsafe {
+        sexp_define_foreign_proc_aux(
+            ctx,
+            env,
+            name,
+            num_args,
+            SEXP_PROC_NONE as raw::c_int,
+            name,
+            f,
+            ptr::null_mut(),
+        )
+    }
 }
 
-pub fn sexp_string_data(x: sexp) -> *const raw::c_char {
+pub fn sexp_define_foreign_proc_rest(
+    ctx: sexp,
+    env: sexp,
+    name: *const raw::c_char,
+    num_args: raw::c_int,
+    f: sexp_proc1,
+) -> sexp {
     unsafe {
-        sexp_bytes_data((*x).value.string.as_ref().bytes).offset(sexp_string_offset(x) as isize)
+        sexp_define_foreign_proc_aux(
+            ctx,
+            env,
+            name,
+            num_args,
+            SEXP_PROC_VARIADIC as raw::c_int,
+            name,
+            f,
+            ptr::null_mut(),
+        )
     }
 }
 
-pub fn sexp_string_length(x: sexp) -> sexp_uint_t {
-    unsafe { (*x).value.string.as_ref().length }
+pub fn sexp_open_input_string(ctx: sexp, s: sexp) -> sexp {
+    unsafe { sexp_open_input_string_op(ctx, ptr::null_mut(), 1, s) }
 }
 
-pub fn sexp_equalp(ctx: sexp, a: sexp, b: sexp) -> sexp {
-    unsafe { sexp_equalp_op(ctx, ptr::null_mut(), 2, a, b) }
+pub fn sexp_open_output_string(ctx: sexp) -> sexp {
+    unsafe { sexp_open_output_string_op(ctx, ptr::null_mut(), 0) }
 }
 
-pub fn sexp_symbol_to_string(ctx: sexp, s: sexp) -> sexp {
-    unsafe { sexp_symbol_to_string_op(ctx, ptr::null_mut(), 1, s) }
+pub fn sexp_get_output_string(ctx: sexp, out: sexp) -> sexp {
+    unsafe { sexp_get_output_string_op(ctx, ptr::null_mut(), 1, out) }
+}
+
+pub fn sexp_read(ctx: sexp, input: sexp) -> sexp {
+    unsafe { sexp_read_op(ctx, ptr::null_mut(), 1, input) }
+}
+
+pub fn sexp_write(ctx: sexp, obj: sexp, out: sexp) -> sexp {
+    unsafe { sexp_write_op(ctx, ptr::null_mut(), 2, obj, out) }
+}
+
+pub fn sexp_display(ctx: sexp, obj: sexp, out: sexp) -> sexp {
+    unsafe { sexp_display_op(ctx, ptr::null_mut(), 2, obj, out) }
+}
+
+pub fn sexp_newline(ctx: sexp, out: sexp) -> sexp {
+    unsafe { sexp_newline_op(ctx, ptr::null_mut(), 1, out) }
+}
+
+pub fn sexp_flush_output(ctx: sexp, out: sexp) -> sexp {
+    unsafe { sexp_flush_output_op(ctx, ptr::null_mut(), 1, out) }
+}
+
+pub fn sexp_close_port(ctx: sexp, port: sexp) -> sexp {
+    unsafe { sexp_close_port_op(ctx, ptr::null_mut(), 1, port) }
+}
+
+pub fn sexp_isa(a: sexp, t: sexp) -> bool {
+    sexp_pointerp(a) && sexp_typep(t) && sexp_pointer_tag(a) == sexp_type_tag(t)
+}
+
+pub fn sexp_make_env(ctx: sexp) -> sexp {
+    unsafe { sexp_make_env_op(ctx, ptr::null_mut(), 0) }
+}
+
+pub fn sexp_make_null_env(ctx: sexp, version: sexp) -> sexp {
+    unsafe { sexp_make_null_env_op(ctx, ptr::null_mut(), 1, version) }
+}
+
+pub fn sexp_make_primitive_env(ctx: sexp, version: sexp) -> sexp {
+    unsafe { sexp_make_primitive_env_op(ctx, ptr::null_mut(), 1, version) }
+}
+
+pub fn sexp_make_standard_env(ctx: sexp, version: sexp) -> sexp {
+    unsafe { sexp_make_standard_env_op(ctx, ptr::null_mut(), 1, version) }
+}
+
+pub fn sexp_add_module_directory(ctx: sexp, dir: sexp, appendp: sexp) -> sexp {
+    unsafe { sexp_add_module_directory_op(ctx, ptr::null_mut(), 2, dir, appendp) }
+}
+
+// `sexp_gc_preserve`: roots `*var` in the context until the matching
+// `sexp_gc_release`. `save` is linked into the context's list of saved
+// variables, so neither it nor `var` may move in between, and releases have
+// to happen in the reverse order of preserves.
+pub fn sexp_gc_preserve(ctx: sexp, var: *mut sexp, save: *mut sexp_gc_var_t) {
+    unsafe {
+        *var = SEXP_VOID;
+        (*save).var = var;
+        (*save).next = sexp_context_saves(ctx);
+        (*ctx).value.context.as_mut().saves = save;
+    }
+}
+
+pub fn sexp_gc_release(ctx: sexp, save: *mut sexp_gc_var_t) {
+    unsafe { (*ctx).value.context.as_mut().saves = (*save).next }
+}
+
+// `sexp_context_env(ctx) = env`: the environment code is evaluated in when no
+// other is given.
+pub fn sexp_context_env_set(ctx: sexp, env: sexp) {
+    unsafe { (*ctx).value.context.as_mut().env = env }
 }
 
-// TODO: Safe accessor
-// TODO: Add feature for stuff
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    // Asserts that the Rust port of a macro agrees with the C one.
+    macro_rules! same {
+        ($f:ident($($arg:expr),*)) => {
+            assert_eq!(
+                macros::$f($($arg),*),
+                shim::$f($($arg),*),
+                "{}",
+                stringify!($f($($arg),*))
+            )
+        };
+    }
+
+    const SAMPLES: &[&str] = &[
+        "1",
+        "-1",
+        "1.5",
+        "2.0",
+        "(expt 10 40)",
+        "(- (expt 10 40))",
+        "1/3",
+        "(make-rectangular 1 2)",
+        "#\\a",
+        "#\\x3bb",
+        "#t",
+        "#f",
+        "'()",
+        "(eof-object)",
+        "(if #f #f)",
+        "'(1 2 3)",
+        "\"str\"",
+        "'sym",
+        "(string->symbol \"a very long symbol that is not immediate\")",
+        "(vector 1 2 3)",
+        "(bytevector 1 2 255)",
+        "(lambda (x . y) x)",
+        "(lambda (x y) x)",
+        "car",
+        "(current-output-port)",
+        "(open-input-string \"\")",
+        "(open-input-bytevector (bytevector 1))",
+        "(delay 1)",
+        "(let ((p (delay 1))) (force p) p)",
+        "(guard (e (#t e)) (error \"message\" 1 2))",
+        "(begin (define-record-type point (make-point x y) point? (x point-x) (y point-y)) \
+          (make-point 1 2))",
+    ];
+
+    struct Scheme {
+        ctx: sexp,
+    }
+
+    impl Scheme {
+        fn new() -> Scheme {
+            unsafe {
+                let ctx =
+                    sexp_make_eval_context(ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), 0, 0);
+                sexp_load_standard_env(ctx, ptr::null_mut(), SEXP_SEVEN);
+                sexp_load_standard_ports(
+                    ctx,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    0,
+                );
+                Scheme { ctx }
+            }
+        }
+
+        fn eval(&self, code: &str) -> sexp {
+            let c_str = CString::new(code).unwrap();
+            let result = unsafe {
+                sexp_eval_string(self.ctx, c_str.as_ptr(), code.len() as _, ptr::null_mut())
+            };
+            unsafe { sexp_preserve_object(self.ctx, result) };
+            result
+        }
+    }
+
+    impl Drop for Scheme {
+        fn drop(&mut self) {
+            unsafe { sexp_destroy_context(self.ctx) };
+        }
+    }
+
+    #[test]
+    fn immediates() {
+        for &n in &[0, 1, -1, SEXP_MAX_FIXNUM as i64, SEXP_MIN_FIXNUM as i64] {
+            let x = sexp_make_fixnum(n);
+            same!(sexp_unbox_fixnum(x));
+            assert_eq!(macros::sexp_unbox_fixnum(x), n as sexp_sint_t);
+        }
+        for &c in &[0, 'a' as raw::c_int, 127, 'λ' as raw::c_int, 0x10ffff] {
+            same!(sexp_make_character(c));
+            same!(sexp_unbox_character(macros::sexp_make_character(c)));
+        }
+        for &b in &[true, false] {
+            same!(sexp_make_boolean(b));
+            same!(sexp_unbox_boolean(macros::sexp_make_boolean(b)));
+        }
+    }
+
+    #[test]
+    fn predicates() {
+        let scheme = Scheme::new();
+        for code in SAMPLES {
+            let x = scheme.eval(code);
+            same!(sexp_truep(x));
+            same!(sexp_not(x));
+            same!(sexp_nullp(x));
+            same!(sexp_eofp(x));
+            same!(sexp_fixnump(x));
+            same!(sexp_flonump(x));
+            same!(sexp_bignump(x));
+            same!(sexp_ratiop(x));
+            same!(sexp_complexp(x));
+            same!(sexp_exact_integerp(x));
+            same!(sexp_integerp(x));
+            same!(sexp_realp(x));
+            same!(sexp_numberp(x));
+            same!(sexp_charp(x));
+            same!(sexp_booleanp(x));
+            same!(sexp_pointerp(x));
+            same!(sexp_isymbolp(x));
+            same!(sexp_lsymbolp(x));
+            same!(sexp_symbolp(x));
+            same!(sexp_pairp(x));
+            same!(sexp_stringp(x));
+            same!(sexp_vectorp(x));
+            same!(sexp_bytesp(x));
+            same!(sexp_procedurep(x));
+            same!(sexp_opcodep(x));
+            same!(sexp_applicablep(x));
+            same!(sexp_iportp(x));
+            same!(sexp_oportp(x));
+            same!(sexp_portp(x));
+            same!(sexp_typep(x));
+            same!(sexp_cpointerp(x));
+            same!(sexp_promisep(x));
+            same!(sexp_envp(x));
+            same!(sexp_exceptionp(x));
+            if sexp_pointerp(x) {
+                same!(sexp_pointer_tag(x));
+                same!(sexp_object_type(scheme.ctx, x));
+                same!(sexp_object_type_name(scheme.ctx, x));
+            }
+        }
+    }
+
+    #[test]
+    fn strings_and_symbols() {
+        let scheme = Scheme::new();
+        let s = scheme.eval("(string-copy \"hello, world\" 7)");
+        same!(sexp_string_bytes(s));
+        same!(sexp_string_offset(s));
+        same!(sexp_string_size(s));
+        same!(sexp_string_length(s));
+        same!(sexp_string_data(s));
+        let bytes = sexp_string_bytes(s);
+        same!(sexp_bytes_length(bytes));
+        same!(sexp_bytes_data(bytes));
+        let sym = scheme.eval("(string->symbol \"a very long symbol that is not immediate\")");
+        if sexp_lsymbolp(sym) {
+            same!(sexp_lsymbol_data(sym));
+            same!(sexp_lsymbol_length(sym));
+        }
+    }
+
+    #[test]
+    fn pairs() {
+        let scheme = Scheme::new();
+        let x = scheme.eval("'(1 2 3)");
+        same!(sexp_car(x));
+        same!(sexp_cdr(x));
+        assert_eq!(sexp_cadr(x), sexp_make_fixnum(2));
+        assert_eq!(sexp_caddr(x), sexp_make_fixnum(3));
+        assert_eq!(sexp_cdddr(x), SEXP_NULL);
+        let list = unsafe { sexp_list2(scheme.ctx, SEXP_TRUE, SEXP_FALSE) };
+        assert_eq!(sexp_car(list), SEXP_TRUE);
+        assert_eq!(sexp_cadr(list), SEXP_FALSE);
+    }
+
+    #[test]
+    fn vectors() {
+        let scheme = Scheme::new();
+        let x = scheme.eval("(vector 1 2 3)");
+        let one = sexp_make_fixnum(1);
+        same!(sexp_vector_length(x));
+        same!(sexp_vector_data(x));
+        same!(sexp_vector_ref(x, one));
+        macros::sexp_vector_set(x, one, SEXP_TRUE);
+        assert_eq!(shim::sexp_vector_ref(x, one), SEXP_TRUE);
+        shim::sexp_vector_set(x, one, SEXP_FALSE);
+        assert_eq!(macros::sexp_vector_ref(x, one), SEXP_FALSE);
+
+        let made = sexp_make_vector(scheme.ctx, sexp_make_fixnum(2), SEXP_TRUE);
+        assert_eq!(sexp_vector_length(made), 2);
+        assert_eq!(sexp_vector_ref(made, one), SEXP_TRUE);
+    }
+
+    #[test]
+    fn bytevectors() {
+        let scheme = Scheme::new();
+        let x = scheme.eval("(bytevector 1 2 255)");
+        let two = sexp_make_fixnum(2);
+        same!(sexp_bytes_length(x));
+        same!(sexp_bytes_ref(x, two));
+        assert_eq!(sexp_bytes_ref(x, two), sexp_make_fixnum(255));
+        macros::sexp_bytes_set(x, two, sexp_make_fixnum(7));
+        assert_eq!(shim::sexp_bytes_ref(x, two), sexp_make_fixnum(7));
+        shim::sexp_bytes_set(x, two, sexp_make_fixnum(8));
+        assert_eq!(macros::sexp_bytes_ref(x, two), sexp_make_fixnum(8));
+    }
+
+    #[test]
+    fn numbers() {
+        let scheme = Scheme::new();
+        let flonum = scheme.eval("1.5");
+        let big = scheme.eval("(- (expt 10 40))");
+        let ratio = scheme.eval("1/3");
+        let complex = scheme.eval("(make-rectangular 1 2)");
+        same!(sexp_flonum_value(flonum));
+        if sexp_bignump(big) {
+            same!(sexp_bignum_sign(big));
+            same!(sexp_bignum_length(big));
+            same!(sexp_bignum_data(big));
+        }
+        if sexp_ratiop(ratio) {
+            same!(sexp_ratio_numerator(ratio));
+            same!(sexp_ratio_denominator(ratio));
+        }
+        if sexp_complexp(complex) {
+            same!(sexp_complex_real(complex));
+            same!(sexp_complex_imag(complex));
+        }
+    }
+
+    #[test]
+    fn procedures() {
+        let scheme = Scheme::new();
+        for code in &["(lambda (x . y) x)", "(lambda (x y) x)"] {
+            let closure = scheme.eval(code);
+            same!(sexp_procedure_flags(closure));
+            same!(sexp_procedure_num_args(closure));
+            same!(sexp_procedure_variadic_p(closure));
+            same!(sexp_procedure_unused_rest_p(closure));
+            same!(sexp_procedure_variable_transformer_p(closure));
+            same!(sexp_procedure_code(closure));
+            same!(sexp_procedure_vars(closure));
+            same!(sexp_procedure_source(closure));
+            same!(sexp_bytecode_name(sexp_procedure_code(closure)));
+        }
+        assert!(sexp_procedure_variadic_p(scheme.eval("(lambda (x . y) x)")));
+
+        for code in &["car", "+", "display"] {
+            let opcode = scheme.eval(code);
+            same!(sexp_opcode_class(opcode));
+            same!(sexp_opcode_code(opcode));
+            same!(sexp_opcode_num_args(opcode));
+            same!(sexp_opcode_flags(opcode));
+            same!(sexp_opcode_inverse(opcode));
+            same!(sexp_opcode_name(opcode));
+            same!(sexp_opcode_data(opcode));
+            same!(sexp_opcode_data2(opcode));
+            same!(sexp_opcode_proc(opcode));
+            same!(sexp_opcode_return_type(opcode));
+            same!(sexp_opcode_arg1_type(opcode));
+            same!(sexp_opcode_arg2_type(opcode));
+            same!(sexp_opcode_arg3_type(opcode));
+            same!(sexp_opcode_argn_type(opcode));
+            same!(sexp_opcode_methods(opcode));
+            same!(sexp_opcode_variadic_p(opcode));
+            same!(sexp_opcode_opt_param_p(opcode));
+            same!(sexp_opcode_ref_trans_p(opcode));
+            same!(sexp_opcode_static_param_p(opcode));
+            same!(sexp_opcode_tail_call_p(opcode));
+            assert_eq!(
+                macros::sexp_opcode_func(opcode).map(|f| f as usize),
+                shim::sexp_opcode_func(opcode).map(|f| f as usize)
+            );
+        }
+
+        let car = scheme.eval("car");
+        let args = sexp_list1(scheme.ctx, scheme.eval("'(1 2)"));
+        assert_eq!(unsafe { sexp_apply(scheme.ctx, car, args) }, sexp_make_fixnum(1));
+    }
+
+    #[test]
+    fn ports() {
+        let scheme = Scheme::new();
+        for code in &["(open-input-bytevector (bytevector 1))", "(current-output-port)"] {
+            let port = scheme.eval(code);
+            same!(sexp_port_stream(port));
+            same!(sexp_port_name(port));
+            same!(sexp_port_line(port));
+            same!(sexp_port_openp(port));
+            same!(sexp_port_binaryp(port));
+            same!(sexp_port_cookie(port));
+            same!(sexp_port_buf(port));
+            same!(sexp_port_size(port));
+            same!(sexp_port_offset(port));
+        }
+
+        let out = sexp_open_output_string(scheme.ctx);
+        unsafe { sexp_preserve_object(scheme.ctx, out) };
+        sexp_display(scheme.ctx, scheme.eval("'(a \"b\")"), out);
+        let s = sexp_get_output_string(scheme.ctx, out);
+        let expected = scheme.eval("\"(a b)\"");
+        assert_eq!(sexp_equalp(scheme.ctx, s, expected), SEXP_TRUE);
+    }
+
+    #[test]
+    fn records() {
+        let scheme = Scheme::new();
+        let point = scheme.eval(SAMPLES[SAMPLES.len() - 1]);
+        let ty = sexp_object_type(scheme.ctx, point);
+        same!(sexp_type_tag(ty));
+        same!(sexp_type_field_base(ty));
+        same!(sexp_type_field_len_base(ty));
+        same!(sexp_type_name(ty));
+        same!(sexp_type_cpl(ty));
+        same!(sexp_type_slots(ty));
+        same!(sexp_type_getters(ty));
+        same!(sexp_type_setters(ty));
+        same!(sexp_type_by_index(scheme.ctx, sexp_type_tag(ty)));
+        assert!(sexp_isa(point, ty));
+        same!(sexp_slot_ref(point, 0));
+        same!(sexp_slot_ref(point, 1));
+        assert_eq!(sexp_slot_ref(point, 1), sexp_make_fixnum(2));
+        macros::sexp_slot_set(point, 0, SEXP_TRUE);
+        assert_eq!(shim::sexp_slot_ref(point, 0), SEXP_TRUE);
+        shim::sexp_slot_set(point, 0, SEXP_FALSE);
+        assert_eq!(macros::sexp_slot_ref(point, 0), SEXP_FALSE);
+    }
+
+    #[test]
+    fn contexts_and_environments() {
+        let scheme = Scheme::new();
+        same!(sexp_context_globals(scheme.ctx));
+        same!(sexp_context_env(scheme.ctx));
+        same!(sexp_context_saves(scheme.ctx));
+        same!(sexp_context_types(scheme.ctx));
+        same!(sexp_global(scheme.ctx, sexp_context_globals_SEXP_G_TYPES));
+        let env = sexp_context_env(scheme.ctx);
+        same!(sexp_env_parent(env));
+        same!(sexp_env_bindings(env));
+        same!(sexp_env_lambda(env));
+        let cell = sexp_env_bindings(env);
+        same!(sexp_pair_source(cell));
+        same!(sexp_env_next_cell(cell));
+        let other = sexp_make_null_env(scheme.ctx, SEXP_SEVEN);
+        sexp_context_env_set(scheme.ctx, other);
+        assert_eq!(other, sexp_context_env(scheme.ctx));
+        sexp_context_env_set(scheme.ctx, env);
+    }
+
+    #[test]
+    fn promises_and_exceptions() {
+        let scheme = Scheme::new();
+        for code in &["(delay 1)", "(let ((p (delay 1))) (force p) p)"] {
+            let promise = scheme.eval(code);
+            same!(sexp_promise_donep(promise));
+            same!(sexp_promise_value(promise));
+        }
+        let exception = scheme.eval("(guard (e (#t e)) (error \"message\" 1 2))");
+        same!(sexp_exception_kind(exception));
+        same!(sexp_exception_message(exception));
+        same!(sexp_exception_irritants(exception));
+        same!(sexp_exception_procedure(exception));
+        same!(sexp_exception_source(exception));
+    }
+
+    #[test]
+    fn cpointers() {
+        let scheme = Scheme::new();
+        let mut value = 0u8;
+        let value = &mut value as *mut u8 as *mut raw::c_void;
+        let x = unsafe {
+            sexp_make_cpointer(scheme.ctx, sexp_types_SEXP_CPOINTER as _, value, SEXP_FALSE, 0)
+        };
+        same!(sexp_cpointer_value(x));
+        same!(sexp_cpointer_maybe_null_value(x));
+        same!(sexp_cpointer_maybe_null_value(SEXP_FALSE));
+        same!(sexp_cpointer_length(x));
+        same!(sexp_cpointer_parent(x));
+        assert_eq!(sexp_cpointer_value(x), value);
+    }
+
+    #[test]
+    fn gc_preserve() {
+        let scheme = Scheme::new();
+        let mut var: sexp = ptr::null_mut();
+        let mut save: sexp_gc_var_t = unsafe { mem::zeroed() };
+        let saves = sexp_context_saves(scheme.ctx);
+        sexp_gc_preserve(scheme.ctx, &mut var, &mut save);
+        assert_eq!(sexp_context_saves(scheme.ctx), &mut save as *mut _);
+        var = sexp_make_vector(scheme.ctx, sexp_make_fixnum(1), SEXP_TRUE);
+        unsafe { sexp_gc(scheme.ctx, ptr::null_mut()) };
+        assert_eq!(sexp_vector_ref(var, sexp_make_fixnum(0)), SEXP_TRUE);
+        sexp_gc_release(scheme.ctx, &mut save);
+        assert_eq!(sexp_context_saves(scheme.ctx), saves);
+    }
+}
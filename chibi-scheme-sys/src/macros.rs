@@ -0,0 +1,605 @@
+// Rust ports of the sexp.h macros. Each has a C counterpart in the generated
+// shim (see SHIMS in build.rs), which the tests in lib.rs compare it against.
+
+use super::*;
+
+pub fn sexp_unbox_fixnum(n: sexp) -> sexp_sint_t {
+    (n as sexp_sint_t) >> SEXP_FIXNUM_BITS
+}
+
+pub fn sexp_flonum_value(x: sexp) -> f64 {
+    unsafe { *(*x).value.flonum.as_ref() }
+}
+
+pub fn sexp_make_character(n: raw::c_int) -> sexp {
+    (((n as sexp_sint_t) << SEXP_EXTENDED_BITS) + (SEXP_CHAR_TAG as sexp_sint_t)) as sexp
+}
+
+pub fn sexp_unbox_character(n: sexp) -> raw::c_int {
+    ((n as sexp_sint_t) >> SEXP_EXTENDED_BITS) as raw::c_int
+}
+
+pub fn sexp_make_boolean(x: bool) -> sexp {
+    if x {
+        SEXP_TRUE
+    } else {
+        SEXP_FALSE
+    }
+}
+
+pub fn sexp_unbox_boolean(x: sexp) -> bool {
+    x != SEXP_FALSE
+}
+
+pub fn sexp_truep(x: sexp) -> bool {
+    x != SEXP_FALSE
+}
+
+pub fn sexp_not(x: sexp) -> bool {
+    x == SEXP_FALSE
+}
+
+pub fn sexp_nullp(x: sexp) -> bool {
+    x == SEXP_NULL
+}
+
+pub fn sexp_eofp(x: sexp) -> bool {
+    x == SEXP_EOF
+}
+
+pub fn sexp_fixnump(x: sexp) -> bool {
+    ((x as sexp_uint_t) & SEXP_FIXNUM_MASK as sexp_uint_t) == SEXP_FIXNUM_TAG as sexp_uint_t
+}
+
+pub fn sexp_flonump(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_FLONUM)
+}
+
+pub fn sexp_exceptionp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_EXCEPTION)
+}
+
+pub fn sexp_exception_message(x: sexp) -> sexp {
+    unsafe { (*x).value.exception.as_ref().message }
+}
+
+pub fn sexp_isymbolp(x: sexp) -> bool {
+    ((x as sexp_uint_t) & SEXP_IMMEDIATE_MASK as sexp_uint_t) == SEXP_ISYMBOL_TAG as sexp_uint_t
+}
+
+pub fn sexp_lsymbolp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_SYMBOL)
+}
+
+pub fn sexp_symbolp(x: sexp) -> bool {
+    sexp_isymbolp(x) || sexp_lsymbolp(x)
+}
+
+pub fn sexp_charp(x: sexp) -> bool {
+    ((x as sexp_uint_t) & SEXP_EXTENDED_MASK as sexp_uint_t) == SEXP_CHAR_TAG as sexp_uint_t
+}
+
+pub fn sexp_exact_integerp(x: sexp) -> bool {
+    sexp_fixnump(x) || sexp_bignump(x)
+}
+
+// Integral flonums such as `2.0` are integers too, as chibi is always built
+// with flonums here.
+pub fn sexp_integerp(x: sexp) -> bool {
+    sexp_exact_integerp(x)
+        || (sexp_flonump(x) && sexp_flonum_value(x) == sexp_flonum_value(x).trunc())
+}
+
+#[cfg(feature = "bignums")]
+pub fn sexp_bignump(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_BIGNUM)
+}
+
+#[cfg(not(feature = "bignums"))]
+pub fn sexp_bignump(_x: sexp) -> bool {
+    false
+}
+
+#[cfg(feature = "ratios")]
+pub fn sexp_ratiop(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_RATIO)
+}
+
+#[cfg(not(feature = "ratios"))]
+pub fn sexp_ratiop(_x: sexp) -> bool {
+    false
+}
+
+#[cfg(feature = "complex")]
+pub fn sexp_complexp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_COMPLEX)
+}
+
+#[cfg(not(feature = "complex"))]
+pub fn sexp_complexp(_x: sexp) -> bool {
+    false
+}
+
+pub fn sexp_realp(x: sexp) -> bool {
+    sexp_exact_integerp(x) || sexp_flonump(x) || sexp_ratiop(x)
+}
+
+pub fn sexp_numberp(x: sexp) -> bool {
+    sexp_realp(x) || sexp_complexp(x)
+}
+
+pub fn sexp_booleanp(x: sexp) -> bool {
+    x == SEXP_TRUE || x == SEXP_FALSE
+}
+
+pub fn sexp_pointerp(x: sexp) -> bool {
+    ((x as sexp_uint_t) & SEXP_POINTER_MASK as sexp_uint_t) == SEXP_POINTER_TAG as sexp_uint_t
+}
+
+pub fn sexp_pointer_tag(x: sexp) -> sexp_tag_t {
+    unsafe { (*x).tag }
+}
+
+pub fn sexp_check_tag(x: sexp, t: sexp_tag_t) -> bool {
+    sexp_pointerp(x) && (sexp_pointer_tag(x) == t)
+}
+
+pub fn sexp_stringp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_STRING)
+}
+
+pub fn sexp_envp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_ENV)
+}
+
+pub fn sexp_pairp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_PAIR)
+}
+
+pub fn sexp_car(x: sexp) -> sexp {
+    unsafe { (*x).value.pair.as_ref().car }
+}
+
+pub fn sexp_cdr(x: sexp) -> sexp {
+    unsafe { (*x).value.pair.as_ref().cdr }
+}
+
+pub fn sexp_pair_source(x: sexp) -> sexp {
+    unsafe { (*x).value.pair.as_ref().source }
+}
+
+pub fn sexp_string_size(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.string.as_ref().length }
+}
+
+pub fn sexp_bytes_length(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.bytes.as_ref().length }
+}
+
+pub fn sexp_bytes_data(x: sexp) -> *mut raw::c_char {
+    unsafe { (*x).value.bytes.as_mut().data.as_mut_ptr() }
+}
+
+pub fn sexp_string_offset(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.string.as_ref().offset }
+}
+
+pub fn sexp_string_bytes(x: sexp) -> sexp {
+    unsafe { (*x).value.string.as_ref().bytes }
+}
+
+pub fn sexp_string_data(x: sexp) -> *mut raw::c_char {
+    unsafe { sexp_bytes_data(sexp_string_bytes(x)).offset(sexp_string_offset(x) as isize) }
+}
+
+pub fn sexp_string_length(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.string.as_ref().length }
+}
+
+pub fn sexp_lsymbol_data(x: sexp) -> *mut raw::c_char {
+    unsafe { (*x).value.symbol.as_mut().data.as_mut_ptr() }
+}
+
+pub fn sexp_lsymbol_length(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.symbol.as_ref().length }
+}
+
+pub fn sexp_vectorp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_VECTOR)
+}
+
+pub fn sexp_vector_length(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.vector.as_ref().length }
+}
+
+pub fn sexp_vector_data(x: sexp) -> *mut sexp {
+    unsafe { (*x).value.vector.as_mut().data.as_mut_ptr() }
+}
+
+pub fn sexp_vector_ref(x: sexp, i: sexp) -> sexp {
+    unsafe { *sexp_vector_data(x).offset(sexp_unbox_fixnum(i) as isize) }
+}
+
+pub fn sexp_vector_set(x: sexp, i: sexp, v: sexp) {
+    unsafe { *sexp_vector_data(x).offset(sexp_unbox_fixnum(i) as isize) = v }
+}
+
+pub fn sexp_bytesp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_BYTES)
+}
+
+pub fn sexp_bytes_ref(x: sexp, i: sexp) -> sexp {
+    let byte = unsafe { *sexp_bytes_data(x).offset(sexp_unbox_fixnum(i) as isize) };
+    sexp_make_fixnum(byte as u8 as i64)
+}
+
+pub fn sexp_bytes_set(x: sexp, i: sexp, v: sexp) {
+    unsafe {
+        *sexp_bytes_data(x).offset(sexp_unbox_fixnum(i) as isize) =
+            sexp_unbox_fixnum(v) as raw::c_char
+    }
+}
+
+pub fn sexp_bignum_sign(x: sexp) -> raw::c_schar {
+    unsafe { (*x).value.bignum.as_ref().sign }
+}
+
+pub fn sexp_bignum_length(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.bignum.as_ref().length }
+}
+
+pub fn sexp_bignum_data(x: sexp) -> *mut sexp_uint_t {
+    unsafe { (*x).value.bignum.as_mut().data.as_mut_ptr() }
+}
+
+pub fn sexp_ratio_numerator(x: sexp) -> sexp {
+    unsafe { (*x).value.ratio.as_ref().numerator }
+}
+
+pub fn sexp_ratio_denominator(x: sexp) -> sexp {
+    unsafe { (*x).value.ratio.as_ref().denominator }
+}
+
+pub fn sexp_complex_real(x: sexp) -> sexp {
+    unsafe { (*x).value.complex.as_ref().real }
+}
+
+pub fn sexp_complex_imag(x: sexp) -> sexp {
+    unsafe { (*x).value.complex.as_ref().imag }
+}
+
+pub fn sexp_procedurep(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_PROCEDURE)
+}
+
+pub fn sexp_opcodep(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_OPCODE)
+}
+
+pub fn sexp_applicablep(x: sexp) -> bool {
+    sexp_procedurep(x) || sexp_opcodep(x)
+}
+
+pub fn sexp_procedure_flags(x: sexp) -> raw::c_char {
+    unsafe { (*x).value.procedure.as_ref().flags }
+}
+
+pub fn sexp_procedure_num_args(x: sexp) -> sexp_proc_num_args_t {
+    unsafe { (*x).value.procedure.as_ref().num_args }
+}
+
+pub fn sexp_procedure_code(x: sexp) -> sexp {
+    unsafe { (*x).value.procedure.as_ref().bc }
+}
+
+pub fn sexp_procedure_vars(x: sexp) -> sexp {
+    unsafe { (*x).value.procedure.as_ref().vars }
+}
+
+// The flags are stored as the low byte of a boxed fixnum.
+fn sexp_procedure_flag(x: sexp, flag: u32) -> bool {
+    sexp_unbox_fixnum(sexp_procedure_flags(x) as sexp_sint_t as sexp) & flag as sexp_sint_t != 0
+}
+
+pub fn sexp_procedure_variadic_p(x: sexp) -> bool {
+    sexp_procedure_flag(x, SEXP_PROC_VARIADIC)
+}
+
+pub fn sexp_procedure_unused_rest_p(x: sexp) -> bool {
+    sexp_procedure_flag(x, SEXP_PROC_UNUSED_REST)
+}
+
+pub fn sexp_procedure_variable_transformer_p(x: sexp) -> bool {
+    sexp_procedure_flag(x, SEXP_PROC_VARIABLE_TRANSFORMER)
+}
+
+pub fn sexp_bytecode_name(x: sexp) -> sexp {
+    unsafe { (*x).value.bytecode.as_ref().name }
+}
+
+pub fn sexp_bytecode_source(x: sexp) -> sexp {
+    unsafe { (*x).value.bytecode.as_ref().source }
+}
+
+pub fn sexp_procedure_source(x: sexp) -> sexp {
+    sexp_bytecode_source(sexp_procedure_code(x))
+}
+
+pub fn sexp_opcode_class(x: sexp) -> raw::c_uchar {
+    unsafe { (*x).value.opcode.as_ref().op_class }
+}
+
+pub fn sexp_opcode_code(x: sexp) -> raw::c_uchar {
+    unsafe { (*x).value.opcode.as_ref().code }
+}
+
+pub fn sexp_opcode_num_args(x: sexp) -> raw::c_uchar {
+    unsafe { (*x).value.opcode.as_ref().num_args }
+}
+
+pub fn sexp_opcode_flags(x: sexp) -> raw::c_uchar {
+    unsafe { (*x).value.opcode.as_ref().flags }
+}
+
+pub fn sexp_opcode_inverse(x: sexp) -> raw::c_uchar {
+    unsafe { (*x).value.opcode.as_ref().inverse }
+}
+
+pub fn sexp_opcode_name(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().name }
+}
+
+pub fn sexp_opcode_data(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().data }
+}
+
+pub fn sexp_opcode_data2(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().data2 }
+}
+
+pub fn sexp_opcode_proc(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().proc_ }
+}
+
+pub fn sexp_opcode_return_type(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().ret_type }
+}
+
+pub fn sexp_opcode_arg1_type(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().arg1_type }
+}
+
+pub fn sexp_opcode_arg2_type(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().arg2_type }
+}
+
+pub fn sexp_opcode_arg3_type(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().arg3_type }
+}
+
+pub fn sexp_opcode_argn_type(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().argn_type }
+}
+
+pub fn sexp_opcode_methods(x: sexp) -> sexp {
+    unsafe { (*x).value.opcode.as_ref().methods }
+}
+
+pub fn sexp_opcode_func(x: sexp) -> sexp_proc1 {
+    unsafe { (*x).value.opcode.as_ref().func }
+}
+
+pub fn sexp_opcode_variadic_p(x: sexp) -> bool {
+    sexp_opcode_flags(x) & 1 != 0
+}
+
+pub fn sexp_opcode_opt_param_p(x: sexp) -> bool {
+    sexp_opcode_flags(x) & 2 != 0
+}
+
+pub fn sexp_opcode_ref_trans_p(x: sexp) -> bool {
+    sexp_opcode_flags(x) & 4 != 0
+}
+
+pub fn sexp_opcode_static_param_p(x: sexp) -> bool {
+    sexp_opcode_flags(x) & 8 != 0
+}
+
+pub fn sexp_opcode_tail_call_p(x: sexp) -> bool {
+    sexp_opcode_flags(x) & 16 != 0
+}
+
+pub fn sexp_iportp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_IPORT)
+}
+
+pub fn sexp_oportp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_OPORT)
+}
+
+pub fn sexp_portp(x: sexp) -> bool {
+    sexp_iportp(x) || sexp_oportp(x)
+}
+
+pub fn sexp_port_stream(x: sexp) -> *mut FILE {
+    unsafe { (*x).value.port.as_ref().stream }
+}
+
+pub fn sexp_port_name(x: sexp) -> sexp {
+    unsafe { (*x).value.port.as_ref().name }
+}
+
+pub fn sexp_port_line(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.port.as_ref().line }
+}
+
+pub fn sexp_port_openp(x: sexp) -> bool {
+    unsafe { (*x).value.port.as_ref().openp != 0 }
+}
+
+pub fn sexp_port_binaryp(x: sexp) -> bool {
+    unsafe { (*x).value.port.as_ref().binaryp != 0 }
+}
+
+pub fn sexp_port_cookie(x: sexp) -> sexp {
+    unsafe { (*x).value.port.as_ref().cookie }
+}
+
+pub fn sexp_port_buf(x: sexp) -> *mut raw::c_char {
+    unsafe { (*x).value.port.as_ref().buf }
+}
+
+pub fn sexp_port_size(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.port.as_ref().size as sexp_uint_t }
+}
+
+pub fn sexp_port_offset(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.port.as_ref().offset }
+}
+
+pub fn sexp_exception_kind(x: sexp) -> sexp {
+    unsafe { (*x).value.exception.as_ref().kind }
+}
+
+pub fn sexp_exception_irritants(x: sexp) -> sexp {
+    unsafe { (*x).value.exception.as_ref().irritants }
+}
+
+pub fn sexp_exception_procedure(x: sexp) -> sexp {
+    unsafe { (*x).value.exception.as_ref().procedure }
+}
+
+pub fn sexp_exception_source(x: sexp) -> sexp {
+    unsafe { (*x).value.exception.as_ref().source }
+}
+
+pub fn sexp_typep(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_TYPE)
+}
+
+pub fn sexp_type_tag(x: sexp) -> sexp_tag_t {
+    unsafe { (*x).value.type_.as_ref().tag }
+}
+
+pub fn sexp_type_field_base(x: sexp) -> raw::c_short {
+    unsafe { (*x).value.type_.as_ref().field_base }
+}
+
+pub fn sexp_type_field_len_base(x: sexp) -> raw::c_short {
+    unsafe { (*x).value.type_.as_ref().field_len_base }
+}
+
+pub fn sexp_type_name(x: sexp) -> sexp {
+    unsafe { (*x).value.type_.as_ref().name }
+}
+
+pub fn sexp_type_cpl(x: sexp) -> sexp {
+    unsafe { (*x).value.type_.as_ref().cpl }
+}
+
+pub fn sexp_type_slots(x: sexp) -> sexp {
+    unsafe { (*x).value.type_.as_ref().slots }
+}
+
+pub fn sexp_type_getters(x: sexp) -> sexp {
+    unsafe { (*x).value.type_.as_ref().getters }
+}
+
+pub fn sexp_type_setters(x: sexp) -> sexp {
+    unsafe { (*x).value.type_.as_ref().setters }
+}
+
+pub fn sexp_context_globals(ctx: sexp) -> sexp {
+    unsafe { (*ctx).value.context.as_ref().globals }
+}
+
+pub fn sexp_context_env(ctx: sexp) -> sexp {
+    unsafe { (*ctx).value.context.as_ref().env }
+}
+
+pub fn sexp_context_saves(ctx: sexp) -> *mut sexp_gc_var_t {
+    unsafe { (*ctx).value.context.as_ref().saves }
+}
+
+pub fn sexp_global(ctx: sexp, x: sexp_context_globals) -> sexp {
+    unsafe { *sexp_vector_data(sexp_context_globals(ctx)).offset(x as isize) }
+}
+
+pub fn sexp_context_types(ctx: sexp) -> *mut sexp {
+    sexp_vector_data(sexp_global(ctx, sexp_context_globals_SEXP_G_TYPES))
+}
+
+pub fn sexp_type_by_index(ctx: sexp, i: sexp_tag_t) -> sexp {
+    unsafe { *sexp_context_types(ctx).offset(i as isize) }
+}
+
+pub fn sexp_object_type(ctx: sexp, x: sexp) -> sexp {
+    sexp_type_by_index(ctx, sexp_pointer_tag(x))
+}
+
+pub fn sexp_object_type_name(ctx: sexp, x: sexp) -> sexp {
+    sexp_type_name(sexp_object_type(ctx, x))
+}
+
+// Records and other user-defined types keep their fields as an array of
+// sexps in place of the value union.
+pub fn sexp_slot_ref(x: sexp, i: sexp_uint_t) -> sexp {
+    unsafe { *(&mut (*x).value as *mut _ as *mut sexp).offset(i as isize) }
+}
+
+pub fn sexp_slot_set(x: sexp, i: sexp_uint_t, v: sexp) {
+    unsafe { *(&mut (*x).value as *mut _ as *mut sexp).offset(i as isize) = v }
+}
+
+pub fn sexp_cpointerp(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_CPOINTER)
+}
+
+pub fn sexp_cpointer_value(x: sexp) -> *mut raw::c_void {
+    unsafe { (*x).value.cpointer.as_ref().value }
+}
+
+pub fn sexp_cpointer_maybe_null_value(x: sexp) -> *mut raw::c_void {
+    if sexp_not(x) {
+        ptr::null_mut()
+    } else {
+        sexp_cpointer_value(x)
+    }
+}
+
+pub fn sexp_cpointer_length(x: sexp) -> sexp_uint_t {
+    unsafe { (*x).value.cpointer.as_ref().length }
+}
+
+pub fn sexp_cpointer_parent(x: sexp) -> sexp {
+    unsafe { (*x).value.cpointer.as_ref().parent }
+}
+
+pub fn sexp_promisep(x: sexp) -> bool {
+    sexp_check_tag(x, sexp_types_SEXP_PROMISE)
+}
+
+pub fn sexp_promise_donep(x: sexp) -> bool {
+    unsafe { (*x).value.promise.as_ref().donep != 0 }
+}
+
+pub fn sexp_promise_value(x: sexp) -> sexp {
+    unsafe { (*x).value.promise.as_ref().value }
+}
+
+pub fn sexp_env_parent(x: sexp) -> sexp {
+    unsafe { (*x).value.env.as_ref().parent }
+}
+
+pub fn sexp_env_bindings(x: sexp) -> sexp {
+    unsafe { (*x).value.env.as_ref().bindings }
+}
+
+pub fn sexp_env_lambda(x: sexp) -> sexp {
+    unsafe { (*x).value.env.as_ref().lambda }
+}
+
+// The bindings of an environment are cells chained through their source.
+pub fn sexp_env_next_cell(x: sexp) -> sexp {
+    sexp_pair_source(x)
+}
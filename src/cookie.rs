@@ -0,0 +1,207 @@
+// `FILE` streams backed by a Rust reader or writer, the same way chibi's own
+// custom ports in (chibi io) are made: fopencookie on Linux and funopen on the
+// BSDs and macOS, with the boxed reader or writer as the cookie. Closing the
+// stream flushes and drops it. Elsewhere opening a stream returns null.
+use chibi_scheme_sys::FILE;
+use std::ffi::c_void;
+use std::io::{self, Read, Write};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+extern "C" {
+    fn setvbuf(stream: *mut FILE, buf: *mut c_char, mode: c_int, size: usize) -> c_int;
+    fn fclose(stream: *mut FILE) -> c_int;
+}
+
+const _IONBF: c_int = 2;
+
+pub fn reader<R: Read + 'static>(r: R) -> *mut FILE {
+    let cookie = Box::into_raw(Box::new(r)) as *mut c_void;
+    let stream = unsafe { platform::open(cookie, Some(read::<R>), None, close::<R>) };
+    if stream.is_null() {
+        drop(unsafe { Box::from_raw(cookie as *mut R) });
+    }
+    stream
+}
+
+// Unbuffered, so that output reaches `w` as soon as chibi writes it.
+pub fn writer<W: Write + 'static>(w: W) -> *mut FILE {
+    let cookie = Box::into_raw(Box::new(w)) as *mut c_void;
+    let stream = unsafe { platform::open(cookie, None, Some(write::<W>), close_writer::<W>) };
+    if stream.is_null() {
+        drop(unsafe { Box::from_raw(cookie as *mut W) });
+    } else {
+        unsafe { setvbuf(stream, ptr::null_mut(), _IONBF, 0) };
+    }
+    stream
+}
+
+// Closes a stream that no port took over, dropping its reader or writer.
+pub fn discard(stream: *mut FILE) {
+    unsafe { fclose(stream) };
+}
+
+// A panic must not unwind into C, so it is reported as an I/O error.
+fn guard(mut f: impl FnMut() -> io::Result<usize>) -> platform::Result {
+    match panic::catch_unwind(AssertUnwindSafe(|| loop {
+        match f() {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    })) {
+        Ok(Ok(n)) => n as platform::Result,
+        _ => -1,
+    }
+}
+
+// The size is an int for funopen.
+#[allow(clippy::unnecessary_cast)]
+extern "C" fn read<R: Read>(
+    cookie: *mut c_void,
+    buf: *mut c_char,
+    size: platform::Size,
+) -> platform::Result {
+    let r = unsafe { &mut *(cookie as *mut R) };
+    let buf = unsafe { slice::from_raw_parts_mut(buf as *mut u8, size as usize) };
+    guard(|| r.read(buf))
+}
+
+#[allow(clippy::unnecessary_cast)]
+extern "C" fn write<W: Write>(
+    cookie: *mut c_void,
+    buf: *const c_char,
+    size: platform::Size,
+) -> platform::Result {
+    let w = unsafe { &mut *(cookie as *mut W) };
+    let buf = unsafe { slice::from_raw_parts(buf as *const u8, size as usize) };
+    guard(|| w.write(buf))
+}
+
+extern "C" fn close<T>(cookie: *mut c_void) -> c_int {
+    let dropped =
+        panic::catch_unwind(AssertUnwindSafe(|| drop(unsafe { Box::from_raw(cookie as *mut T) })));
+    if dropped.is_ok() {
+        0
+    } else {
+        -1
+    }
+}
+
+extern "C" fn close_writer<W: Write>(cookie: *mut c_void) -> c_int {
+    let w = unsafe { &mut *(cookie as *mut W) };
+    let flushed = guard(|| w.flush().map(|_| 0));
+    let closed = close::<W>(cookie);
+    if flushed == 0 && closed == 0 {
+        0
+    } else {
+        -1
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod platform {
+    use super::*;
+
+    pub type Size = usize;
+    pub type Result = isize;
+    type ReadFn = extern "C" fn(*mut c_void, *mut c_char, Size) -> Result;
+    type WriteFn = extern "C" fn(*mut c_void, *const c_char, Size) -> Result;
+    type SeekFn = extern "C" fn(*mut c_void, *mut i64, c_int) -> c_int;
+    type CloseFn = extern "C" fn(*mut c_void) -> c_int;
+
+    #[repr(C)]
+    struct CookieIoFunctions {
+        read: Option<ReadFn>,
+        write: Option<WriteFn>,
+        seek: Option<SeekFn>,
+        close: Option<CloseFn>,
+    }
+
+    extern "C" {
+        fn fopencookie(
+            cookie: *mut c_void,
+            mode: *const c_char,
+            functions: CookieIoFunctions,
+        ) -> *mut FILE;
+    }
+
+    pub unsafe fn open(
+        cookie: *mut c_void,
+        read: Option<ReadFn>,
+        write: Option<WriteFn>,
+        close: CloseFn,
+    ) -> *mut FILE {
+        let mode = if read.is_some() { "r\0" } else { "w\0" };
+        let functions = CookieIoFunctions { read, write, seek: None, close: Some(close) };
+        fopencookie(cookie, mode.as_ptr() as *const c_char, functions)
+    }
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+))]
+mod platform {
+    use super::*;
+
+    pub type Size = c_int;
+    pub type Result = c_int;
+    type ReadFn = extern "C" fn(*mut c_void, *mut c_char, Size) -> Result;
+    type WriteFn = extern "C" fn(*mut c_void, *const c_char, Size) -> Result;
+    type SeekFn = extern "C" fn(*mut c_void, i64, c_int) -> i64;
+    type CloseFn = extern "C" fn(*mut c_void) -> c_int;
+
+    extern "C" {
+        fn funopen(
+            cookie: *const c_void,
+            read: Option<ReadFn>,
+            write: Option<WriteFn>,
+            seek: Option<SeekFn>,
+            close: Option<CloseFn>,
+        ) -> *mut FILE;
+    }
+
+    pub unsafe fn open(
+        cookie: *mut c_void,
+        read: Option<ReadFn>,
+        write: Option<WriteFn>,
+        close: CloseFn,
+    ) -> *mut FILE {
+        funopen(cookie, read, write, None, Some(close))
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly"
+)))]
+mod platform {
+    use super::*;
+
+    pub type Size = usize;
+    pub type Result = isize;
+    type ReadFn = extern "C" fn(*mut c_void, *mut c_char, Size) -> Result;
+    type WriteFn = extern "C" fn(*mut c_void, *const c_char, Size) -> Result;
+    type CloseFn = extern "C" fn(*mut c_void) -> c_int;
+
+    pub unsafe fn open(
+        _cookie: *mut c_void,
+        _read: Option<ReadFn>,
+        _write: Option<WriteFn>,
+        _close: CloseFn,
+    ) -> *mut FILE {
+        ptr::null_mut()
+    }
+}
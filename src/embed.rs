@@ -0,0 +1,121 @@
+// The module tree compiled in by the `embed-lib` feature. chibi's module
+// loader only reads files from the directories on its module path, so the
+// embedded files are written out into a directory named after a hash of their
+// contents, and that directory is put in front of the module path. Nothing is
+// read from the install prefix chibi-scheme was built with.
+//
+// This writes to the filesystem: the tree lives in a directory of the system
+// temporary directory (`TMPDIR` on Unix) that only the current user can
+// access, and is left there for the next process to reuse. Anything already
+// there is compared with the embedded copy before it is used, so another user
+// cannot put their own modules in its place, and a temporary directory that
+// cannot be written to makes `Context::standard_env` fail.
+use chibi_scheme_sys::EMBEDDED_LIB;
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// The directory the tree is extracted to, which `extract` fills.
+pub fn module_dir() -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    EMBEDDED_LIB.hash(&mut hasher);
+    private_root().join(format!("lib-{:016x}", hasher.finish()))
+}
+
+pub fn extract(dir: &Path) -> Result<(), String> {
+    write_tree(dir).map_err(|e| {
+        format!("could not extract the embedded module tree to {}: {}", dir.display(), e)
+    })
+}
+
+fn private_root() -> PathBuf {
+    env::temp_dir().join(format!("chibi-scheme-{}", user_id()))
+}
+
+fn write_tree(dir: &Path) -> io::Result<()> {
+    let private = private_dir(&private_root())?;
+    if dir.is_dir() {
+        if matches(dir)? {
+            return Ok(());
+        }
+        fs::remove_dir_all(dir)?;
+    }
+
+    // Write into a directory of this process first, so that a concurrent
+    // process never sees a half-written tree.
+    let name = dir.file_name().unwrap().to_string_lossy();
+    let staging = private.join(format!("{}.{}", name, process::id()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    for (file, contents) in EMBEDDED_LIB {
+        let path = staging.join(file);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, contents)?;
+    }
+    match fs::rename(&staging, dir) {
+        Ok(()) => Ok(()),
+        Err(_) if matches(dir)? => fs::remove_dir_all(&staging),
+        Err(e) => Err(e),
+    }
+}
+
+// Whether every embedded file is in `dir` with the same contents.
+fn matches(dir: &Path) -> io::Result<bool> {
+    for (file, contents) in EMBEDDED_LIB {
+        match fs::read(dir.join(file)) {
+            Ok(ref found) if found.as_slice() == *contents => {}
+            Ok(_) => return Ok(false),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+// Creates `dir` readable only by the current user, or checks that an
+// existing one is a directory of theirs that nobody else can write to.
+#[cfg(unix)]
+fn private_dir(dir: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != user_id() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory owned by the current user", dir.display()),
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir.to_path_buf())
+}
+
+#[cfg(not(unix))]
+fn private_dir(dir: &Path) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    Ok(dir.to_path_buf())
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    extern "C" {
+        fn getuid() -> u32;
+    }
+    unsafe { getuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> u32 {
+    0
+}
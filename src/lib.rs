@@ -1,4 +1,9 @@
 pub mod serde;
 pub mod sexp;
 
+mod cookie;
+
+#[cfg(feature = "embed-lib")]
+mod embed;
+
 extern crate serde as lib_serde;
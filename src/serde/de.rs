@@ -1,47 +1,46 @@
 use crate::serde::error::{Error, Result};
-use crate::sexp::{
-    Bool, Char, Context, Exception, Integer, Null, Pair, Rational, SExp, String, Symbol,
-};
+use crate::sexp::{Number, NumberError, SExp, String};
 use serde::de::{self, DeserializeSeed, MapAccess, Visitor};
 use serde::Deserialize;
-use std::cell;
 use std::convert::TryFrom;
-use std::num::TryFromIntError;
 
 pub struct Deserializer<'c> {
     input: SExp<'c>,
 }
 
 impl<'de> Deserializer<'de> {
-    fn deserialize_integer<V>(&self, visitor: V) -> Result<V::Value>
+    fn deserialize_integer<T>(&self) -> Result<T>
     where
-        V: Visitor<'de>,
+        T: for<'n> TryFrom<Number<'n>, Error = NumberError>,
     {
-        match &self.input {
-            SExp::Integer(i) => visitor.visit_i64(i.into()),
-            o => Err(Error::ExpectedInteger(format!("{:?}", o))),
-        }
+        let expected = || Error::ExpectedInteger(format!("{:?}", self.input));
+        let number = Number::try_from(&self.input).map_err(|_| expected())?;
+        T::try_from(number).map_err(|e| match e {
+            NumberError::OutOfRange { .. } => Error::IntegerTooLargeForBytes(e),
+            _ => expected(),
+        })
     }
 
-    fn deserialize_rational<V>(&self, visitor: V) -> Result<V::Value>
+    fn deserialize_rational<T>(&self) -> Result<T>
     where
-        V: Visitor<'de>,
+        T: for<'n> TryFrom<Number<'n>, Error = NumberError>,
     {
-        match &self.input {
-            SExp::Rational(i) => visitor.visit_f64(i.into()),
-            o => Err(Error::ExpectedRational(format!("{:?}", o))),
-        }
+        let number = Number::try_from(&self.input)
+            .map_err(|_| Error::ExpectedRational(format!("{:?}", self.input)))?;
+        T::try_from(number).map_err(|e| Error::ExpectedRational(e.to_string()))
     }
 
-    fn deserialize_char<V>(&self, visitor: V) -> Result<V::Value>
+    fn deserialize_character<V>(&self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
-        // match &self.input {
-        //     SExp::Char(c) => visitor.visit_char(c.into() as char),
-        //     o => Err(Error::ExpectedChar(format!("{:?}", o))),
-        // }
+        match &self.input {
+            SExp::Char(c) => match char::try_from(c) {
+                Ok(c) => visitor.visit_char(c),
+                Err(_) => Err(Error::ExpectedChar(format!("{:?}", c))),
+            },
+            o => Err(Error::ExpectedChar(format!("{:?}", o))),
+        }
     }
 
     fn deserialize_sstring<V>(&self, visitor: V) -> Result<V::Value>
@@ -50,10 +49,28 @@ impl<'de> Deserializer<'de> {
     {
         match &self.input {
             SExp::String(s) => visitor.visit_string(s.into()),
+            // So that the symbol keys of an alist or hash table can be strings.
+            SExp::Symbol(s) => visitor.visit_string((&String::from(s)).into()),
             o => Err(Error::ExpectedString(format!("{:?}", o))),
         }
     }
 
+    // Alists are read in place; hash tables through their alist.
+    fn deserialize_assoc<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match &self.input {
+            SExp::HashTable(t) => {
+                let mut alist = Deserializer {
+                    input: t.to_alist().map_err(|e| Error::Message(format!("{:?}", e)))?,
+                };
+                visitor.visit_map(AssocList { de: &mut alist })
+            }
+            _ => visitor.visit_map(AssocList { de: self }),
+        }
+    }
+
     fn deserialize_symbol<V>(&self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
@@ -101,56 +118,70 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_i8(self.deserialize_integer()?)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_i16(self.deserialize_integer()?)
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_i32(self.deserialize_integer()?)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_i64(self.deserialize_integer()?)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_u8(self.deserialize_integer()?)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_u16(self.deserialize_integer()?)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_u32(self.deserialize_integer()?)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_integer(visitor)
+        visitor.visit_u64(self.deserialize_integer()?)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i128(self.deserialize_integer()?)
+    }
+
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u128(self.deserialize_integer()?)
     }
 
     // Float parsing is stupidly hard.
@@ -158,7 +189,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_rational(visitor)
+        visitor.visit_f32(self.deserialize_rational()?)
     }
 
     // Float parsing is stupidly hard.
@@ -166,14 +197,13 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        self.deserialize_rational(visitor)
+        visitor.visit_f64(self.deserialize_rational()?)
     }
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        // TODO: Need to go from a c char to a char
-        unimplemented!()
+        self.deserialize_character(visitor)
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
@@ -189,19 +219,24 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     {
         self.deserialize_sstring(visitor)
     }
-    // arrays as JSON arrays of bytes. Handle that representation here.
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        match &self.input {
+            SExp::Bytevector(b) => visitor.visit_bytes(b.data()),
+            o => Err(Error::ExpectedBytevector(format!("{:?}", o))),
+        }
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        match &self.input {
+            SExp::Bytevector(b) => visitor.visit_byte_buf(b.into()),
+            o => Err(Error::ExpectedBytevector(format!("{:?}", o))),
+        }
     }
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
@@ -254,11 +289,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     {
         unimplemented!()
     }
-    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        self.deserialize_assoc(visitor)
     }
     fn deserialize_struct<V>(
         self,
@@ -269,8 +304,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let assoc_list = AssocList { de: self };
-        visitor.visit_map(assoc_list)
+        self.deserialize_assoc(visitor)
     }
     fn deserialize_enum<V>(
         self,
@@ -402,6 +436,53 @@ mod tests {
         assert_all(&mut assertions)
     }
 
+    #[test]
+    fn test_deserialize_bigint() {
+        let context = Context::default();
+        let mut assertions: Vec<(SExp, u64)> =
+            vec![(context.eval_string("(- (expt 2 64) 1)").unwrap(), u64::MAX)];
+        assert_all(&mut assertions);
+
+        let mut assertions: Vec<(SExp, i128)> = vec![
+            (context.eval_string("(expt 2 100)").unwrap(), 1 << 100),
+            (context.eval_string("(- (expt 2 127))").unwrap(), i128::MIN),
+        ];
+        assert_all(&mut assertions);
+
+        let mut assertions: Vec<(SExp, u128)> =
+            vec![(context.eval_string("(- (expt 2 128) 1)").unwrap(), u128::MAX)];
+        assert_all(&mut assertions);
+
+        assert!(de::from_sexp::<i64>(context.eval_string("(expt 2 64)").unwrap()).is_err());
+        assert!(de::from_sexp::<u128>(context.eval_string("(expt 2 128)").unwrap()).is_err());
+        assert!(de::from_sexp::<u64>(context.eval_string("(- (expt 2 64))").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_out_of_range() {
+        let context = Context::default();
+        assert_eq!(
+            Err(de::Error::IntegerTooLargeForBytes(sexp::NumberError::OutOfRange {
+                value: "-1".to_string(),
+                target: "u32",
+            })),
+            de::from_sexp::<u32>(Integer::from(-1).into())
+        );
+        assert_eq!(
+            "300 is out of range for u8",
+            de::from_sexp::<u8>(Integer::from(300).into())
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            Err(de::Error::ExpectedInteger("1.5".to_string())),
+            de::from_sexp::<i32>(context.flonum(1.5).into())
+        );
+        assert!(de::from_sexp::<f32>(context.flonum(1e300).into()).is_err());
+        assert_eq!(Ok(2.0), de::from_sexp::<f64>(Integer::from(2).into()));
+        assert_eq!(Ok(0.25), de::from_sexp::<f64>(context.eval_string("1/4").unwrap()));
+    }
+
     #[test]
     fn test_deserialize_f64() {
         let context = Context::default();
@@ -424,6 +505,26 @@ mod tests {
         assert_all(&mut assertions)
     }
 
+    #[test]
+    fn test_deserialize_char() {
+        let context = Context::default();
+        let mut assertions: Vec<(SExp, char)> = vec![
+            (SExp::from(sexp::Char::from('a')), 'a'),
+            (SExp::from(sexp::Char::from('λ')), 'λ'),
+            (context.eval_string("#\\space").unwrap(), ' '),
+        ];
+        assert_all(&mut assertions);
+
+        assert_eq!(
+            Err(de::Error::ExpectedChar("#\\xd800".to_string())),
+            de::from_sexp::<char>(context.eval_string("(integer->char #xd800)").unwrap())
+        );
+        assert_eq!(
+            Err(de::Error::ExpectedChar("\"a\"".to_string())),
+            de::from_sexp::<char>(context.string("a").into())
+        );
+    }
+
     #[test]
     fn test_deserialize_string() {
         let context = Context::default();
@@ -435,6 +536,23 @@ mod tests {
         assert_all(&mut assertions)
     }
 
+    #[test]
+    fn test_deserialize_bytes() {
+        use serde_bytes::ByteBuf;
+
+        let context = Context::default();
+        let mut assertions: Vec<(SExp, ByteBuf)> = vec![
+            (SExp::from(context.bytevector(&[])), ByteBuf::from(vec![])),
+            (SExp::from(context.bytevector(&[0, 1, 255])), ByteBuf::from(vec![0, 1, 255])),
+        ];
+        assert_all(&mut assertions);
+
+        assert_eq!(
+            Err(de::Error::ExpectedBytevector("\"a\"".to_string())),
+            de::from_sexp::<ByteBuf>(context.string("a").into())
+        );
+    }
+
     #[test]
     fn test_deserialize_struct() {
         let context = Context::default();
@@ -488,6 +606,36 @@ mod tests {
         assert_eq!(expected, de::from_sexp(bar).unwrap());
     }
 
+    #[test]
+    fn test_deserialize_map() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.eval_string("(import (srfi 69))").unwrap();
+        let mut expected = std::collections::HashMap::new();
+        expected.insert("foo".to_string(), 1);
+        expected.insert("bar".to_string(), 2);
+
+        let alist = context.eval_string("'((foo . 1) (bar . 2))").unwrap();
+        assert_eq!(Ok(expected.clone()), de::from_sexp(alist));
+
+        let table = context
+            .eval_string(
+                "(let ((t (make-hash-table))) \
+                 (hash-table-set! t 'foo 1) (hash-table-set! t \"bar\" 2) t)",
+            )
+            .unwrap();
+        assert_eq!(Ok(expected), de::from_sexp(table));
+
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Foo {
+            foo: i32,
+        }
+        let table = context
+            .eval_string("(let ((t (make-hash-table))) (hash-table-set! t 'foo 3) t)")
+            .unwrap();
+        assert_eq!(Ok(Foo { foo: 3 }), de::from_sexp(table));
+    }
+
     fn assert_all<'a, T>(table: &mut Vec<(SExp<'a>, T)>)
     where
         T: Deserialize<'a>,
@@ -1,7 +1,7 @@
 use lib_serde::{de, ser};
 use std;
 use std::fmt::{self, Display};
-use std::num::TryFromIntError;
+use crate::sexp::NumberError;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -12,11 +12,12 @@ pub enum Error {
     Message(String),
     ExpectedBoolean(String),
     ExpectedInteger(String),
-    IntegerTooLargeForBytes(TryFromIntError),
+    IntegerTooLargeForBytes(NumberError),
     ExpectedRational(String),
     ExpectedSymbol(String),
     ExpectedChar(String),
     ExpectedString(String),
+    ExpectedBytevector(String),
     ExpectedPairOrEndOfAssocList(String),
     ExpectedPair(String),
 }
@@ -35,7 +36,10 @@ impl de::Error for Error {
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(std::error::Error::description(self))
+        match self {
+            Error::IntegerTooLargeForBytes(cause) => cause.fmt(formatter),
+            _ => formatter.write_str(std::error::Error::description(self)),
+        }
     }
 }
 
@@ -49,6 +53,7 @@ impl std::error::Error for Error {
             Error::ExpectedSymbol(ref msg) => msg,
             Error::ExpectedChar(ref msg) => msg,
             Error::ExpectedString(ref msg) => msg,
+            Error::ExpectedBytevector(ref msg) => msg,
             Error::ExpectedPairOrEndOfAssocList(ref msg) => msg,
             Error::ExpectedPair(ref msg) => msg,
             Error::DeserializeAnyNotSupported => &"Deserialize any not supported",
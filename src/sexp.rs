@@ -1,12 +1,24 @@
 use chibi_scheme_derive::SExp;
 use chibi_scheme_sys::*;
+use std::char::CharTryFromError;
+use std::cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error;
 use std::ffi;
 use std::fmt;
+use std::hash;
+use std::io;
+use std::mem;
 use std::ops;
 use std::os::raw;
+use std::panic;
+use std::process;
 use std::ptr;
+use std::rc;
 use std::slice;
 use std::string::String as RustString;
+use std::vec;
 
 pub struct RawSExp<'a> {
     sexp: sexp,
@@ -22,19 +34,93 @@ impl RawSExp<'_> {
     }
 }
 
+// Copies of `sexp`s that the garbage collector treats as live until the roots
+// are dropped, for holding objects across allocations, as `sexp_gc_preserve`
+// does for C locals. Roots have to be dropped in the reverse order they were
+// made in, which locals are.
+struct Roots {
+    context: sexp,
+    vars: Box<[cell::Cell<sexp>]>,
+    saves: Box<[sexp_gc_var_t]>,
+}
+
+impl Roots {
+    fn new(context: &Context, values: &[sexp]) -> Roots {
+        let vars = values.iter().map(|_| cell::Cell::new(SEXP_VOID)).collect::<Box<[_]>>();
+        let mut saves = values
+            .iter()
+            .map(|_| unsafe { mem::zeroed() })
+            .collect::<Box<[sexp_gc_var_t]>>();
+        for (var, save) in vars.iter().zip(saves.iter_mut()) {
+            sexp_gc_preserve(context.0, var.as_ptr(), save);
+        }
+        for (var, &value) in vars.iter().zip(values) {
+            var.set(value);
+        }
+        Roots {
+            context: context.0,
+            vars,
+            saves,
+        }
+    }
+
+    fn get(&self, i: usize) -> sexp {
+        self.vars[i].get()
+    }
+
+    fn set(&self, i: usize, value: sexp) {
+        self.vars[i].set(value)
+    }
+
+    // Converts `value` into slot `i`, unless it converts to an exception.
+    fn convert<'a, T: IntoSExp>(
+        &self,
+        context: &'a Context,
+        i: usize,
+        value: T,
+    ) -> Result<(), SExp<'a>> {
+        match value.into_sexp(context) {
+            e @ SExp::Exception(_) => Err(e),
+            value => {
+                self.set(i, value.sexp);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for Roots {
+    fn drop(&mut self) {
+        for save in self.saves.iter_mut().rev() {
+            sexp_gc_release(self.context, save);
+        }
+    }
+}
+
 #[derive(PartialEq)]
 pub enum SExp<'a> {
     String(String<'a>),
     Bool(Bool),
     Char(Char),
     Integer(Integer),
+    BigInt(BigInt<'a>),
     Rational(Rational<'a>),
+    Ratio(Ratio<'a>),
+    Complex(Complex<'a>),
     Null(Null),
     Symbol(Symbol<'a>),
     Pair(Pair<'a>),
+    Vector(Vector<'a>),
+    Bytevector(Bytevector<'a>),
+    Procedure(Procedure<'a>),
+    Record(Record<'a>),
+    HashTable(HashTable<'a>),
+    Type(Type<'a>),
     Exception(Exception<'a>),
+    Port(Port<'a>),
     Void(Void),
-    Env(Env<'a>)
+    Env(Env<'a>),
+    Opaque(Opaque<'a>),
 }
 
 impl<'a> ops::Deref for SExp<'a> {
@@ -45,13 +131,24 @@ impl<'a> ops::Deref for SExp<'a> {
             SExp::Bool(b) => b,
             SExp::Char(c) => c,
             SExp::Integer(i) => i,
+            SExp::BigInt(b) => b,
             SExp::Rational(r) => r,
+            SExp::Ratio(r) => r,
+            SExp::Complex(c) => c,
             SExp::Null(n) => n,
             SExp::Symbol(s) => s,
             SExp::Pair(p) => p,
+            SExp::Vector(v) => v,
+            SExp::Bytevector(b) => b,
+            SExp::Procedure(p) => p,
+            SExp::Record(r) => r,
+            SExp::HashTable(h) => h,
+            SExp::Type(t) => t,
             SExp::Exception(e) => e,
+            SExp::Port(p) => p,
             SExp::Void(v) => v,
-            SExp::Env(e) => e
+            SExp::Env(e) => e,
+            SExp::Opaque(o) => o,
         }
     }
 }
@@ -62,14 +159,25 @@ impl<'a> fmt::Debug for SExp<'a> {
             SExp::Bool(b) => b.fmt(fmt),
             SExp::Char(c) => c.fmt(fmt),
             SExp::Integer(i) => i.fmt(fmt),
+            SExp::BigInt(b) => b.fmt(fmt),
             SExp::Null(n) => n.fmt(fmt),
             SExp::Pair(p) => p.fmt(fmt),
+            SExp::Vector(v) => v.fmt(fmt),
+            SExp::Bytevector(b) => b.fmt(fmt),
             SExp::String(s) => s.fmt(fmt),
+            SExp::Procedure(p) => p.fmt(fmt),
+            SExp::Record(r) => r.fmt(fmt),
+            SExp::HashTable(h) => h.fmt(fmt),
+            SExp::Type(t) => t.fmt(fmt),
             SExp::Exception(e) => e.fmt(fmt),
+            SExp::Port(p) => p.fmt(fmt),
             SExp::Rational(r) => r.fmt(fmt),
+            SExp::Ratio(r) => r.fmt(fmt),
+            SExp::Complex(c) => c.fmt(fmt),
             SExp::Symbol(s) => s.fmt(fmt),
             SExp::Void(v) => v.fmt(fmt),
-            SExp::Env(e) => e.fmt(fmt)
+            SExp::Env(e) => e.fmt(fmt),
+            SExp::Opaque(o) => o.fmt(fmt),
         }
     }
 }
@@ -102,6 +210,18 @@ impl From<&String<'_>> for RustString {
     }
 }
 
+impl TryFrom<SExp<'_>> for RustString {
+    type Error = RustString;
+
+    fn try_from(sexp: SExp) -> Result<RustString, RustString> {
+        match sexp {
+            SExp::String(s) => Ok(RustString::from(&s)),
+            SExp::Symbol(s) => Ok(RustString::from(&String::from(&s))),
+            o => Err(format!("expected a string, got {:?}", o)),
+        }
+    }
+}
+
 #[derive(SExp)]
 pub struct Pair<'a>(RawSExp<'a>);
 
@@ -151,6 +271,138 @@ impl<'a> fmt::Debug for Pair<'a> {
     }
 }
 
+#[derive(SExp)]
+pub struct Vector<'a>(RawSExp<'a>);
+
+impl<'a> Vector<'a> {
+    pub fn len(&self) -> usize {
+        sexp_vector_length(self.sexp) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Panics if `i` is out of bounds, like indexing a slice.
+    pub fn get(&self, i: usize) -> SExp<'a> {
+        assert!(i < self.len(), "index {} out of bounds for vector of length {}", i, self.len());
+        let sexp = RawSExp {
+            sexp: sexp_vector_ref(self.sexp, sexp_make_fixnum(i as _)),
+            context: self.context,
+        };
+        sexp.into()
+    }
+
+    pub fn set(&mut self, i: usize, value: &SExp<'a>) {
+        assert!(i < self.len(), "index {} out of bounds for vector of length {}", i, self.len());
+        sexp_vector_set(self.sexp, sexp_make_fixnum(i as _), value.sexp)
+    }
+
+    pub fn iter<'b>(&'b self) -> VectorIter<'b, 'a> {
+        VectorIter {
+            vector: self,
+            index: 0,
+        }
+    }
+}
+
+pub struct VectorIter<'b, 'a> {
+    vector: &'b Vector<'a>,
+    index: usize,
+}
+
+impl<'b, 'a> Iterator for VectorIter<'b, 'a> {
+    type Item = SExp<'a>;
+
+    fn next(&mut self) -> Option<SExp<'a>> {
+        if self.index < self.vector.len() {
+            self.index += 1;
+            Some(self.vector.get(self.index - 1))
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.vector.len() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'b, 'a> IntoIterator for &'b Vector<'a> {
+    type Item = SExp<'a>;
+    type IntoIter = VectorIter<'b, 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> fmt::Debug for Vector<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str("#(")?;
+        for (i, sexp) in self.iter().enumerate() {
+            if i > 0 {
+                fmt.write_str(" ")?;
+            }
+            sexp.fmt(fmt)?;
+        }
+        fmt.write_str(")")
+    }
+}
+
+#[derive(SExp)]
+pub struct Bytevector<'a>(RawSExp<'a>);
+
+impl Bytevector<'_> {
+    pub fn len(&self) -> usize {
+        sexp_bytes_length(self.sexp) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn data(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(sexp_bytes_data(self.sexp) as _, self.len()) }
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(sexp_bytes_data(self.sexp) as _, self.len()) }
+    }
+}
+
+impl AsRef<[u8]> for Bytevector<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.data()
+    }
+}
+
+impl AsMut<[u8]> for Bytevector<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.data_mut()
+    }
+}
+
+impl From<&Bytevector<'_>> for Vec<u8> {
+    fn from(b: &Bytevector) -> Vec<u8> {
+        b.data().to_vec()
+    }
+}
+
+impl fmt::Debug for Bytevector<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str("#u8(")?;
+        for (i, byte) in self.data().iter().enumerate() {
+            if i > 0 {
+                fmt.write_str(" ")?;
+            }
+            fmt.write_fmt(format_args!("{}", byte))?;
+        }
+        fmt.write_str(")")
+    }
+}
+
 #[derive(SExp)]
 pub struct Null(RawSExp<'static>);
 
@@ -203,33 +455,79 @@ impl<'a> fmt::Debug for Symbol<'a> {
     }
 }
 
+// A Unicode code point.
 #[derive(SExp)]
 pub struct Char(RawSExp<'static>);
 
+impl Char {
+    pub fn code_point(&self) -> u32 {
+        sexp_unbox_character(self.sexp) as u32
+    }
+}
+
+impl From<char> for Char {
+    fn from(c: char) -> Char {
+        Char(RawSExp::new(sexp_make_character(c as u32 as raw::c_int)))
+    }
+}
+
+// Fails only for surrogates, which `integer->char` accepts but Rust does not.
+impl TryFrom<&Char> for char {
+    type Error = CharTryFromError;
+
+    fn try_from(c: &Char) -> Result<char, CharTryFromError> {
+        char::try_from(c.code_point())
+    }
+}
+
+impl TryFrom<SExp<'_>> for char {
+    type Error = RustString;
+
+    fn try_from(sexp: SExp) -> Result<char, RustString> {
+        match sexp {
+            SExp::Char(c) => {
+                char::try_from(&c).map_err(|_| format!("{:?} is not a Rust char", c))
+            }
+            o => Err(format!("expected a character, got {:?}", o)),
+        }
+    }
+}
+
+// Truncates anything outside a single byte.
 impl From<&Char> for raw::c_char {
     fn from(c: &Char) -> raw::c_char {
-        sexp_unbox_character(c.sexp)
+        sexp_unbox_character(c.sexp) as raw::c_char
     }
 }
 
 impl From<raw::c_char> for Char {
     fn from(c: raw::c_char) -> Char {
-        Char(RawSExp::new(sexp_make_character(c)))
+        Char(RawSExp::new(sexp_make_character(c as u8 as raw::c_int)))
     }
 }
 
 impl fmt::Debug for Char {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt.write_fmt(format_args!(
-            "#\\{}",
-            (raw::c_char::from(self) as u8) as char
-        ))
+        let name = match self.code_point() {
+            0x00 => "null",
+            0x07 => "alarm",
+            0x08 => "backspace",
+            0x09 => "tab",
+            0x0a => "newline",
+            0x0d => "return",
+            0x1b => "escape",
+            0x20 => "space",
+            0x7f => "delete",
+            c @ 0x21..=0x7e => return fmt.write_fmt(format_args!("#\\{}", c as u8 as char)),
+            c => return fmt.write_fmt(format_args!("#\\x{:x}", c)),
+        };
+        fmt.write_fmt(format_args!("#\\{}", name))
     }
 }
 
 impl PartialEq for Char {
     fn eq(self: &Self, rhs: &Self) -> bool {
-        raw::c_char::from(self) == raw::c_char::from(rhs)
+        self.code_point() == rhs.code_point()
     }
 }
 
@@ -245,6 +543,18 @@ impl From<&Bool> for bool {
     }
 }
 
+// Only accepts booleans, although any other value would count as true.
+impl TryFrom<SExp<'_>> for bool {
+    type Error = RustString;
+
+    fn try_from(sexp: SExp) -> Result<bool, RustString> {
+        match sexp {
+            SExp::Bool(b) => Ok(bool::from(&b)),
+            o => Err(format!("expected a boolean, got {:?}", o)),
+        }
+    }
+}
+
 impl PartialEq for Bool {
     fn eq(self: &Self, rhs: &Self) -> bool {
         sexp_truep(self.sexp) && sexp_truep(rhs.sexp) || sexp_not(self.sexp) && sexp_not(rhs.sexp)
@@ -289,180 +599,2101 @@ impl PartialEq for Integer {
     }
 }
 
-// https://groups.csail.mit.edu/mac/ftpdir/scheme-7.4/doc-html/scheme_5.html
+// An integer too large for a fixnum. Chibi normalizes its results, so any
+// value in fixnum range comes back as an `Integer` instead.
 #[derive(SExp)]
-pub struct Rational<'a>(RawSExp<'a>);
+pub struct BigInt<'a>(RawSExp<'a>);
 
-impl From<&Rational<'_>> for f64 {
-    fn from(i: &Rational) -> f64 {
-        sexp_flonum_value(i.sexp)
+impl BigInt<'_> {
+    pub fn is_negative(&self) -> bool {
+        sexp_bignum_sign(self.sexp) < 0
     }
-}
 
-impl<'a> fmt::Debug for Rational<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt.write_fmt(format_args!("{}", f64::from(self)))
+    // The magnitude as little-endian words.
+    fn words(&self) -> &[sexp_uint_t] {
+        let len = sexp_bignum_length(self.sexp) as usize;
+        unsafe { slice::from_raw_parts(sexp_bignum_data(self.sexp), len) }
     }
-}
-
-#[derive(SExp)]
-pub struct Exception<'a>(RawSExp<'a>);
 
-impl Exception<'_> {
-    fn message(&self) -> String {
-        String(RawSExp {
-            sexp: sexp_exception_message(self.sexp),
-            context: self.context,
+    fn magnitude(&self) -> Option<u128> {
+        let bits = mem::size_of::<sexp_uint_t>() * 8;
+        self.words().iter().rev().try_fold(0u128, |acc, &word| {
+            if acc >> (128 - bits) != 0 {
+                None
+            } else {
+                Some(acc << bits | word as u128)
+            }
         })
     }
-}
 
-impl<'a> fmt::Debug for Exception<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt.write_fmt(format_args!("error: {:?}", self.message()))
+    fn out_of_range(&self, target: &'static str) -> NumberError {
+        NumberError::OutOfRange {
+            value: self.to_string(),
+            target: target,
+        }
     }
 }
 
-#[derive(SExp)]
-pub struct Env<'a>(RawSExp<'a>);
+impl TryFrom<&BigInt<'_>> for i128 {
+    type Error = NumberError;
 
-impl<'a> fmt::Debug for Env<'a> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt.write_str("")
+    fn try_from(b: &BigInt) -> Result<i128, NumberError> {
+        let out_of_range = || b.out_of_range("i128");
+        let magnitude = b.magnitude().ok_or_else(out_of_range)?;
+        if b.is_negative() {
+            if magnitude > i128::max_value() as u128 + 1 {
+                Err(out_of_range())
+            } else {
+                Ok((magnitude as i128).wrapping_neg())
+            }
+        } else {
+            i128::try_from(magnitude).map_err(|_| out_of_range())
+        }
     }
 }
 
-pub struct Context(sexp);
+impl TryFrom<&BigInt<'_>> for u128 {
+    type Error = NumberError;
 
-impl<'a> From<RawSExp<'a>> for SExp<'a> {
-    //is the 'static lifetime not the bottom?
-    fn from(sexp: RawSExp<'a>) -> SExp<'a> {
-        if sexp_booleanp(sexp.sexp) {
-            if sexp_truep(sexp.sexp) {
-                TRUE.into()
-            } else {
-                FALSE.into()
-            }
-        } else if sexp.sexp == SEXP_VOID {
-            NULL.into()
-        } else if sexp_charp(sexp.sexp) {
-            Char(RawSExp::new(sexp.sexp)).into()
-        } else if sexp_nullp(sexp.sexp) {
-            NULL.into()
-        } else if sexp_integerp(sexp.sexp) {
-            Integer(RawSExp::new(sexp.sexp)).into()
-        } else if sexp_pairp(sexp.sexp) {
-            Pair(sexp).into()
-        } else if sexp_stringp(sexp.sexp) {
-            String(sexp).into()
-        } else if sexp_flonump(sexp.sexp) {
-            Rational(sexp).into()
-        } else if sexp_symbolp(sexp.sexp) {
-            Symbol(sexp).into()
-        } else if sexp_exceptionp(sexp.sexp) {
-            Exception(sexp).into()
-        } else if sexp_envp(sexp.sexp) {
-            Env(sexp).into()
-        } else {
-            unimplemented!()
-            // panic!("Unexpacted type {:?}", sexp_pointer_tag(sexp.sexp))
+    fn try_from(b: &BigInt) -> Result<u128, NumberError> {
+        match b.magnitude() {
+            Some(magnitude) if !b.is_negative() => Ok(magnitude),
+            _ => Err(b.out_of_range("u128")),
         }
     }
 }
 
-impl Default for Context {
-    fn default() -> Self {
-        //TODO: switch to different default
-        Context(unsafe {
-            sexp_make_eval_context(ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), 0, 0)
-        })
+impl TryFrom<&BigInt<'_>> for i64 {
+    type Error = NumberError;
+
+    fn try_from(b: &BigInt) -> Result<i64, NumberError> {
+        i128::try_from(b)
+            .ok()
+            .and_then(|i| i64::try_from(i).ok())
+            .ok_or_else(|| b.out_of_range("i64"))
     }
 }
 
-impl Drop for Context {
-    fn drop(&mut self) {
-        unsafe { sexp_destroy_context(self.0) };
+impl TryFrom<&BigInt<'_>> for u64 {
+    type Error = NumberError;
+
+    fn try_from(b: &BigInt) -> Result<u64, NumberError> {
+        u128::try_from(b)
+            .ok()
+            .and_then(|u| u64::try_from(u).ok())
+            .ok_or_else(|| b.out_of_range("u64"))
     }
 }
 
-impl Context {
-    pub fn eval_string(&self, str: &str) -> Result<SExp, Exception> {
-        let c_str = unsafe { ffi::CStr::from_bytes_with_nul_unchecked(str.as_bytes()) };
-        let sexp = RawSExp {
-            sexp: unsafe {
-                sexp_eval_string(self.0, c_str.as_ptr(), str.len() as _, ptr::null_mut())
-            },
-            context: Some(self),
-        };
-        if sexp_exceptionp(sexp.sexp) {
-            Err(Exception(sexp).into())
-        } else {
-            Ok(sexp.into())
-        }
+impl From<&BigInt<'_>> for f64 {
+    fn from(b: &BigInt) -> f64 {
+        b.context.unwrap().to_f64(b.sexp)
     }
+}
 
-    pub fn standard_env(&mut self) -> Result<Env, Exception> {
-        let sexp = RawSExp {
-            sexp: unsafe { sexp_load_standard_env(self.0, ptr::null_mut(), SEXP_SEVEN) },
-            context: Some(self),
-        };
-        if sexp_exceptionp(sexp.sexp) {
-            Err(Exception(sexp).into())
+#[cfg(feature = "num-bigint")]
+impl From<&BigInt<'_>> for num_bigint::BigInt {
+    fn from(b: &BigInt) -> num_bigint::BigInt {
+        let sign = if b.is_negative() {
+            num_bigint::Sign::Minus
         } else {
-            Ok(Env(sexp))
-        }
-    }
-    pub fn cons<'a>(&self, a: &'a SExp, b: &'a SExp) -> SExp {
-        let sexp = RawSExp {
-            sexp: sexp_cons(self.0, a.sexp, b.sexp),
-            context: Some(self),
+            num_bigint::Sign::Plus
         };
-        if !sexp_exceptionp(sexp.sexp) {
-            Pair(sexp).into()
-        } else {
-            Exception(sexp).into()
-        }
+        let bytes: Vec<u8> = b.words().iter().flat_map(|w| w.to_le_bytes().to_vec()).collect();
+        num_bigint::BigInt::from_bytes_le(sign, &bytes)
     }
+}
 
-    pub fn flonum(&self, i: f64) -> Rational {
-        let sexp = unsafe { sexp_make_flonum(self.0, i) };
-        Rational(RawSExp {
-            sexp: sexp,
-            context: Some(self),
-        })
+impl fmt::Display for BigInt<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str(&self.context.unwrap().write_string(self.sexp))
     }
+}
 
-    pub fn string(&self, str: &str) -> String {
-        let len = str.len();
-        let c_str = unsafe { ffi::CStr::from_bytes_with_nul_unchecked(str.as_bytes()) };
-        let sexp = unsafe { sexp_c_string(self.0, c_str.as_ptr(), len as _) };
-        String(RawSExp {
-            sexp: sexp,
-            context: Some(self),
-        })
+impl fmt::Debug for BigInt<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(self, fmt)
     }
+}
 
-    pub fn intern(&self, str: &str) -> Symbol {
-        let len = str.len();
-        let c_str = unsafe { ffi::CStr::from_bytes_with_nul_unchecked(str.as_bytes()) };
-        let sexp = unsafe { sexp_intern(self.0, c_str.as_ptr(), len as _) };
-        Symbol(RawSExp {
-            sexp: sexp,
-            context: Some(self),
-        })
+// Why a Scheme number could not be converted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NumberError {
+    OutOfRange {
+        value: RustString,
+        target: &'static str,
+    },
+    InvalidInteger(RustString),
+    NotAnInteger(RustString),
+    NotReal(RustString),
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            NumberError::OutOfRange { value, target } => {
+                fmt.write_fmt(format_args!("{} is out of range for {}", value, target))
+            }
+            NumberError::InvalidInteger(s) => {
+                fmt.write_fmt(format_args!("{:?} is not an integer", s))
+            }
+            NumberError::NotAnInteger(value) => {
+                fmt.write_fmt(format_args!("{} is not an exact integer", value))
+            }
+            NumberError::NotReal(value) => {
+                fmt.write_fmt(format_args!("{} is not a real number", value))
+            }
+        }
     }
 }
 
-mod tests {
+impl error::Error for NumberError {}
+
+// A real number of any representation, for converting to Rust numbers. The
+// integer conversions fail on anything inexact or fractional rather than
+// rounding, and on anything out of the target's range rather than wrapping.
+#[derive(Clone, Copy)]
+pub enum Number<'a> {
+    Integer(&'a Integer),
+    BigInt(&'a BigInt<'a>),
+    Rational(&'a Rational<'a>),
+    Ratio(&'a Ratio<'a>),
+}
 
-    use crate::sexp::*;
+impl<'a> TryFrom<&'a SExp<'a>> for Number<'a> {
+    type Error = NumberError;
 
-    #[test]
-    fn test_pair() {
-        let context = Context::default();
-        assert_eq!(
-            Ok(context.cons(&Char::from('c' as raw::c_char).into(), &FALSE.into())),
-            context.eval_string("'(#\\c . #f)")
+    fn try_from(sexp: &'a SExp<'a>) -> Result<Number<'a>, NumberError> {
+        match sexp {
+            SExp::Integer(i) => Ok(Number::Integer(i)),
+            SExp::BigInt(b) => Ok(Number::BigInt(b)),
+            SExp::Rational(r) => Ok(Number::Rational(r)),
+            SExp::Ratio(r) => Ok(Number::Ratio(r)),
+            o => Err(NumberError::NotReal(format!("{:?}", o))),
+        }
+    }
+}
+
+impl Number<'_> {
+    fn out_of_range(&self, target: &'static str) -> NumberError {
+        NumberError::OutOfRange {
+            value: self.to_string(),
+            target: target,
+        }
+    }
+}
+
+macro_rules! integer_from_number {
+    ($($t:ident),*) => {
+        $(
+            impl TryFrom<Number<'_>> for $t {
+                type Error = NumberError;
+
+                fn try_from(n: Number) -> Result<$t, NumberError> {
+                    let out_of_range = || n.out_of_range(stringify!($t));
+                    match n {
+                        Number::Integer(i) => {
+                            $t::try_from(i64::from(i)).map_err(|_| out_of_range())
+                        }
+                        Number::BigInt(b) if b.is_negative() => i128::try_from(b)
+                            .ok()
+                            .and_then(|i| $t::try_from(i).ok())
+                            .ok_or_else(out_of_range),
+                        Number::BigInt(b) => u128::try_from(b)
+                            .ok()
+                            .and_then(|u| $t::try_from(u).ok())
+                            .ok_or_else(out_of_range),
+                        _ => Err(NumberError::NotAnInteger(n.to_string())),
+                    }
+                }
+            }
+
+            impl TryFrom<SExp<'_>> for $t {
+                type Error = NumberError;
+
+                fn try_from(sexp: SExp) -> Result<$t, NumberError> {
+                    $t::try_from(Number::try_from(&sexp)?)
+                }
+            }
+        )*
+    };
+}
+
+integer_from_number!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl TryFrom<Number<'_>> for f64 {
+    type Error = NumberError;
+
+    fn try_from(n: Number) -> Result<f64, NumberError> {
+        Ok(match n {
+            Number::Integer(i) => i64::from(i) as f64,
+            Number::BigInt(b) => f64::from(b),
+            Number::Rational(r) => f64::from(r),
+            Number::Ratio(r) => f64::from(r),
+        })
+    }
+}
+
+impl TryFrom<Number<'_>> for f32 {
+    type Error = NumberError;
+
+    fn try_from(n: Number) -> Result<f32, NumberError> {
+        let f = f64::try_from(n)?;
+        if f.is_finite() && f.abs() > f32::MAX as f64 {
+            Err(n.out_of_range("f32"))
+        } else {
+            Ok(f as f32)
+        }
+    }
+}
+
+impl TryFrom<SExp<'_>> for f64 {
+    type Error = NumberError;
+
+    fn try_from(sexp: SExp) -> Result<f64, NumberError> {
+        f64::try_from(Number::try_from(&sexp)?)
+    }
+}
+
+impl TryFrom<SExp<'_>> for f32 {
+    type Error = NumberError;
+
+    fn try_from(sexp: SExp) -> Result<f32, NumberError> {
+        f32::try_from(Number::try_from(&sexp)?)
+    }
+}
+
+impl fmt::Display for Number<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Number::Integer(i) => fmt::Debug::fmt(i, fmt),
+            Number::BigInt(b) => fmt::Debug::fmt(b, fmt),
+            Number::Rational(r) => fmt::Debug::fmt(r, fmt),
+            Number::Ratio(r) => fmt::Debug::fmt(r, fmt),
+        }
+    }
+}
+
+impl fmt::Debug for Number<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt::Display::fmt(self, fmt)
+    }
+}
+
+// https://groups.csail.mit.edu/mac/ftpdir/scheme-7.4/doc-html/scheme_5.html
+#[derive(SExp)]
+pub struct Rational<'a>(RawSExp<'a>);
+
+impl From<&Rational<'_>> for f64 {
+    fn from(i: &Rational) -> f64 {
+        sexp_flonum_value(i.sexp)
+    }
+}
+
+impl<'a> fmt::Debug for Rational<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_fmt(format_args!("{}", f64::from(self)))
+    }
+}
+
+// An exact fraction such as `1/3`, always in lowest terms with a positive
+// denominator. Unlike `Rational`, which holds a flonum.
+#[derive(SExp)]
+pub struct Ratio<'a>(RawSExp<'a>);
+
+impl<'a> Ratio<'a> {
+    pub fn numerator<'b>(&'b self) -> SExp<'a> {
+        let sexp = RawSExp {
+            sexp: sexp_ratio_numerator(self.sexp),
+            context: self.context,
+        };
+        sexp.into()
+    }
+
+    pub fn denominator<'b>(&'b self) -> SExp<'a> {
+        let sexp = RawSExp {
+            sexp: sexp_ratio_denominator(self.sexp),
+            context: self.context,
+        };
+        sexp.into()
+    }
+}
+
+impl From<&Ratio<'_>> for f64 {
+    fn from(r: &Ratio) -> f64 {
+        r.context.unwrap().to_f64(r.sexp)
+    }
+}
+
+#[cfg(feature = "num-rational")]
+impl TryFrom<&Ratio<'_>> for num_rational::Rational64 {
+    type Error = NumberError;
+
+    fn try_from(r: &Ratio) -> Result<num_rational::Rational64, NumberError> {
+        Ok(num_rational::Rational64::new_raw(
+            exact_i64(&r.numerator())?,
+            exact_i64(&r.denominator())?,
+        ))
+    }
+}
+
+#[cfg(all(feature = "num-rational", feature = "num-bigint"))]
+impl From<&Ratio<'_>> for num_rational::BigRational {
+    fn from(r: &Ratio) -> num_rational::BigRational {
+        num_rational::BigRational::new_raw(
+            exact_bigint(&r.numerator()),
+            exact_bigint(&r.denominator()),
+        )
+    }
+}
+
+impl fmt::Debug for Ratio<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str(&self.context.unwrap().write_string(self.sexp))
+    }
+}
+
+// The parts of a ratio are always exact integers.
+#[cfg(feature = "num-rational")]
+fn exact_i64(sexp: &SExp) -> Result<i64, NumberError> {
+    match sexp {
+        SExp::Integer(i) => Ok(i.into()),
+        SExp::BigInt(b) => i64::try_from(b),
+        o => unreachable!("{:?} is not an exact integer", o),
+    }
+}
+
+#[cfg(all(feature = "num-rational", feature = "num-bigint"))]
+fn exact_bigint(sexp: &SExp) -> num_bigint::BigInt {
+    match sexp {
+        SExp::Integer(i) => i64::from(i).into(),
+        SExp::BigInt(b) => b.into(),
+        o => unreachable!("{:?} is not an exact integer", o),
+    }
+}
+
+#[derive(SExp)]
+pub struct Complex<'a>(RawSExp<'a>);
+
+impl<'a> Complex<'a> {
+    pub fn real<'b>(&'b self) -> SExp<'a> {
+        let sexp = RawSExp {
+            sexp: sexp_complex_real(self.sexp),
+            context: self.context,
+        };
+        sexp.into()
+    }
+
+    pub fn imag<'b>(&'b self) -> SExp<'a> {
+        let sexp = RawSExp {
+            sexp: sexp_complex_imag(self.sexp),
+            context: self.context,
+        };
+        sexp.into()
+    }
+
+    pub fn re(&self) -> f64 {
+        self.context.unwrap().to_f64(sexp_complex_real(self.sexp))
+    }
+
+    pub fn im(&self) -> f64 {
+        self.context.unwrap().to_f64(sexp_complex_imag(self.sexp))
+    }
+}
+
+#[cfg(feature = "num-complex")]
+impl From<&Complex<'_>> for num_complex::Complex64 {
+    fn from(c: &Complex) -> num_complex::Complex64 {
+        num_complex::Complex64::new(c.re(), c.im())
+    }
+}
+
+impl fmt::Debug for Complex<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str(&self.context.unwrap().write_string(self.sexp))
+    }
+}
+
+// Anything applicable: a closure, a primitive opcode, or a C function
+// registered as a foreign opcode.
+#[derive(SExp)]
+pub struct Procedure<'a>(RawSExp<'a>);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcedureKind {
+    Closure,
+    Opcode,
+    Foreign,
+}
+
+// The arguments a procedure accepts: `required` of them, then up to
+// `optional` more, then any number more if `rest` is set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Arity {
+    pub required: usize,
+    pub optional: usize,
+    pub rest: bool,
+}
+
+impl Arity {
+    pub fn accepts(&self, n: usize) -> bool {
+        n >= self.required && (self.rest || n <= self.required + self.optional)
+    }
+}
+
+impl<'a> Procedure<'a> {
+    pub fn kind(&self) -> ProcedureKind {
+        if sexp_procedurep(self.sexp) {
+            ProcedureKind::Closure
+        } else if sexp_opcode_class(self.sexp) as u32 == sexp_opcode_classes_SEXP_OPC_FOREIGN {
+            ProcedureKind::Foreign
+        } else {
+            ProcedureKind::Opcode
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        if sexp_procedurep(self.sexp) {
+            // A closure's num_args counts its rest parameter.
+            let rest = sexp_procedure_variadic_p(self.sexp);
+            Arity {
+                required: sexp_procedure_num_args(self.sexp) as usize - rest as usize,
+                optional: 0,
+                rest: rest,
+            }
+        } else {
+            // An opcode's optional parameter is counted in its num_args.
+            let optional = sexp_opcode_opt_param_p(self.sexp) as usize;
+            Arity {
+                required: sexp_opcode_num_args(self.sexp) as usize - optional,
+                optional: optional,
+                rest: sexp_opcode_variadic_p(self.sexp),
+            }
+        }
+    }
+
+    // The name it was defined with, if any; anonymous lambdas have none.
+    pub fn name(&self) -> Option<RustString> {
+        let name = RawSExp {
+            sexp: if sexp_procedurep(self.sexp) {
+                sexp_bytecode_name(sexp_procedure_code(self.sexp))
+            } else {
+                sexp_opcode_name(self.sexp)
+            },
+            context: self.context,
+        };
+        match name.into() {
+            SExp::Symbol(s) => Some(RustString::from(&String::from(&s))),
+            SExp::String(s) => Some(RustString::from(&s)),
+            _ => None,
+        }
+    }
+
+    pub fn call(&self, args: &[SExp]) -> Result<SExp<'a>, Exception<'a>> {
+        self.apply(&args.iter().map(|arg| arg.sexp).collect::<Vec<sexp>>())
+    }
+
+    fn apply(&self, args: &[sexp]) -> Result<SExp<'a>, Exception<'a>> {
+        self.context.unwrap().apply(self.sexp, args)
+    }
+}
+
+impl<'a> fmt::Debug for Procedure<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.name() {
+            Some(name) => fmt.write_fmt(format_args!("#<procedure {}>", name)),
+            None => fmt.write_str("#<procedure>"),
+        }
+    }
+}
+
+// Conversion of a Scheme value into a Rust one, describing the value when it
+// does not fit.
+pub trait FromSExp: Sized {
+    fn from_sexp(sexp: SExp) -> Result<Self, RustString>;
+}
+
+// Conversion of a Rust value into a Scheme one allocated in `context`. A value
+// that cannot be represented becomes an `SExp::Exception`, which is raised
+// when returned from a procedure made by `Context::define_fn`.
+pub trait IntoSExp {
+    fn into_sexp(self, context: &Context) -> SExp;
+}
+
+macro_rules! from_sexp_by_try_from {
+    ($($t:ident),*) => {
+        $(
+            impl FromSExp for $t {
+                fn from_sexp(sexp: SExp) -> Result<$t, RustString> {
+                    $t::try_from(sexp).map_err(|e| e.to_string())
+                }
+            }
+        )*
+    };
+}
+
+from_sexp_by_try_from!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+from_sexp_by_try_from!(f32, f64, bool, char, RustString);
+
+macro_rules! integer_into_sexp {
+    ($($t:ident),*) => {
+        $(
+            impl IntoSExp for $t {
+                fn into_sexp(self, context: &Context) -> SExp {
+                    match i64::try_from(self) {
+                        Ok(i) if SEXP_MIN_FIXNUM <= i as sexp_sint_t
+                            && i as sexp_sint_t <= SEXP_MAX_FIXNUM => Integer::from(i).into(),
+                        _ => context.parse_integer(&self.to_string()).unwrap_or_else(|_| {
+                            let message = format!("{} does not fit in a fixnum", self);
+                            context.exception(&message).into()
+                        }),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integer_into_sexp!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+impl IntoSExp for f64 {
+    fn into_sexp(self, context: &Context) -> SExp {
+        context.flonum(self).into()
+    }
+}
+
+impl IntoSExp for f32 {
+    fn into_sexp(self, context: &Context) -> SExp {
+        context.flonum(self as f64).into()
+    }
+}
+
+impl IntoSExp for bool {
+    fn into_sexp(self, _context: &Context) -> SExp {
+        if self {
+            TRUE.into()
+        } else {
+            FALSE.into()
+        }
+    }
+}
+
+impl IntoSExp for char {
+    fn into_sexp(self, _context: &Context) -> SExp {
+        Char::from(self).into()
+    }
+}
+
+impl IntoSExp for &str {
+    fn into_sexp(self, context: &Context) -> SExp {
+        context.string(self).into()
+    }
+}
+
+impl IntoSExp for RustString {
+    fn into_sexp(self, context: &Context) -> SExp {
+        context.string(&self).into()
+    }
+}
+
+impl IntoSExp for () {
+    fn into_sexp(self, _context: &Context) -> SExp {
+        VOID.into()
+    }
+}
+
+// The same value; it must come from the same context.
+impl IntoSExp for &SExp<'_> {
+    fn into_sexp(self, context: &Context) -> SExp {
+        RawSExp {
+            sexp: self.sexp,
+            context: Some(context),
+        }
+        .into()
+    }
+}
+
+macro_rules! immediate_into_sexp {
+    ($($t:ident),*) => {
+        $(
+            impl IntoSExp for $t {
+                fn into_sexp(self, _context: &Context) -> SExp {
+                    self.into()
+                }
+            }
+        )*
+    };
+}
+
+immediate_into_sexp!(Integer, Bool, Char, Null, Void);
+
+// `#f` is `None`, anything else is converted as `T`.
+impl<T: FromSExp> FromSExp for Option<T> {
+    fn from_sexp(sexp: SExp) -> Result<Option<T>, RustString> {
+        match sexp {
+            SExp::Bool(ref b) if !bool::from(b) => Ok(None),
+            sexp => T::from_sexp(sexp).map(Some),
+        }
+    }
+}
+
+impl<T: IntoSExp> IntoSExp for Option<T> {
+    fn into_sexp(self, context: &Context) -> SExp {
+        match self {
+            Some(t) => t.into_sexp(context),
+            None => FALSE.into(),
+        }
+    }
+}
+
+// An `Err` becomes a Scheme error with its message.
+impl<T: IntoSExp, E: fmt::Display> IntoSExp for Result<T, E> {
+    fn into_sexp(self, context: &Context) -> SExp {
+        match self {
+            Ok(t) => t.into_sexp(context),
+            Err(e) => context.exception(&e.to_string()).into(),
+        }
+    }
+}
+
+// The elements of a proper list.
+fn list_elements(sexp: SExp) -> Result<Vec<SExp>, RustString> {
+    let mut elements = Vec::new();
+    let mut tail = sexp;
+    loop {
+        match tail {
+            SExp::Null(_) => return Ok(elements),
+            SExp::Pair(pair) => {
+                elements.push(pair.car());
+                tail = pair.cdr();
+            }
+            o => return Err(format!("expected a list, got {:?}", o)),
+        }
+    }
+}
+
+// Either a list or a vector.
+impl<T: FromSExp> FromSExp for Vec<T> {
+    fn from_sexp(sexp: SExp) -> Result<Vec<T>, RustString> {
+        match sexp {
+            SExp::Vector(vector) => vector.iter().map(T::from_sexp).collect(),
+            sexp => list_elements(sexp)?.into_iter().map(T::from_sexp).collect(),
+        }
+    }
+}
+
+// A list.
+impl<T: IntoSExp> IntoSExp for Vec<T> {
+    fn into_sexp(self, context: &Context) -> SExp {
+        context.list(self.into_iter())
+    }
+}
+
+macro_rules! tuple_sexp {
+    ($n:expr; $($t:ident),*) => {
+        // A list of exactly as many elements.
+        impl<$($t: FromSExp),*> FromSExp for ($($t,)*) {
+            fn from_sexp(sexp: SExp) -> Result<($($t,)*), RustString> {
+                let elements = list_elements(sexp)?;
+                if elements.len() != $n {
+                    return Err(format!("expected {} elements, got {}", $n, elements.len()));
+                }
+                let mut elements = elements.into_iter();
+                Ok(($($t::from_sexp(elements.next().unwrap())?,)*))
+            }
+        }
+
+        impl<$($t: IntoSExp),*> IntoSExp for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn into_sexp(self, context: &Context) -> SExp {
+                let ($($t,)*) = self;
+                let roots = Roots::new(context, &[SEXP_NULL; $n + 1]);
+                let mut i = 0;
+                $(
+                    i += 1;
+                    if let Err(e) = roots.convert(context, i, $t) {
+                        return e;
+                    }
+                )*
+                context.list_of(roots)
+            }
+        }
+    };
+}
+
+tuple_sexp!(1; A);
+tuple_sexp!(2; A, B);
+tuple_sexp!(3; A, B, C);
+tuple_sexp!(4; A, B, C, D);
+tuple_sexp!(5; A, B, C, D, E);
+tuple_sexp!(6; A, B, C, D, E, F);
+
+// Either a hash table or an association list.
+impl<K, V> FromSExp for HashMap<K, V>
+where
+    K: FromSExp + Eq + hash::Hash,
+    V: FromSExp,
+{
+    fn from_sexp(sexp: SExp) -> Result<HashMap<K, V>, RustString> {
+        let alist = match sexp {
+            SExp::HashTable(table) => table.to_alist().map_err(|e| format!("{:?}", e))?,
+            sexp => sexp,
+        };
+        list_elements(alist)?
+            .into_iter()
+            .map(|entry| match entry {
+                SExp::Pair(pair) => Ok((K::from_sexp(pair.car())?, V::from_sexp(pair.cdr())?)),
+                o => Err(format!("expected a key and value pair, got {:?}", o)),
+            })
+            .collect()
+    }
+}
+
+// An `equal?` hash table.
+impl<K: IntoSExp, V: IntoSExp> IntoSExp for HashMap<K, V> {
+    fn into_sexp(self, context: &Context) -> SExp {
+        let table = match context.hash_table(&HashMap::new()) {
+            Ok(table) => table,
+            Err(e) => return e.into(),
+        };
+        // The table, and each key and value as soon as it is converted.
+        let roots = Roots::new(context, &[table.sexp, SEXP_FALSE, SEXP_FALSE]);
+        for (key, value) in self {
+            if let Err(e) = roots.convert(context, 1, key) {
+                return e;
+            }
+            if let Err(e) = roots.convert(context, 2, value) {
+                return e;
+            }
+            if let Err(e) = table.apply("hash-table-set!", &[roots.get(1), roots.get(2)]) {
+                return e.into();
+            }
+        }
+        table.into()
+    }
+}
+
+// A Rust closure that Scheme can call, see `Context::define_fn`. It is
+// implemented for closures of up to six parameters, each an `Arg`, returning
+// anything `IntoSExp`. Its arity is an error when the parameters are not in
+// an order arguments can be given in: required ones, then optional ones,
+// then at most one `Rest`.
+pub trait ForeignFn<Args>: 'static {
+    fn arity() -> Result<Arity, RustString>;
+
+    fn call<'a>(&self, context: &'a Context, args: Vec<SExp<'a>>)
+        -> Result<SExp<'a>, RustString>;
+}
+
+// A parameter of a `ForeignFn`. Any `FromSExp` type takes a required
+// argument, `Optional` an optional one and `Rest` all that remain.
+pub trait Arg: Sized {
+    fn arity() -> Arity;
+
+    fn take(args: &mut vec::IntoIter<SExp>) -> Result<Self, RustString>;
+}
+
+impl<T> Arg for T
+where
+    T: FromSExp,
+{
+    fn arity() -> Arity {
+        Arity {
+            required: 1,
+            optional: 0,
+            rest: false,
+        }
+    }
+
+    fn take(args: &mut vec::IntoIter<SExp>) -> Result<T, RustString> {
+        let arg = args.next().ok_or("missing argument")?;
+        T::from_sexp(arg)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Optional<T>(pub Option<T>);
+
+impl<T> Arg for Optional<T>
+where
+    T: FromSExp,
+{
+    fn arity() -> Arity {
+        Arity {
+            required: 0,
+            optional: 1,
+            rest: false,
+        }
+    }
+
+    fn take(args: &mut vec::IntoIter<SExp>) -> Result<Optional<T>, RustString> {
+        match args.next() {
+            Some(arg) => T::from_sexp(arg).map(|t| Optional(Some(t))),
+            None => Ok(Optional(None)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rest<T>(pub Vec<T>);
+
+impl<T> Arg for Rest<T>
+where
+    T: FromSExp,
+{
+    fn arity() -> Arity {
+        Arity {
+            required: 0,
+            optional: 0,
+            rest: true,
+        }
+    }
+
+    fn take(args: &mut vec::IntoIter<SExp>) -> Result<Rest<T>, RustString> {
+        args.map(T::from_sexp).collect::<Result<Vec<T>, RustString>>().map(Rest)
+    }
+}
+
+macro_rules! foreign_fn {
+    ($($a:ident),*) => {
+        impl<T, R, $($a),*> ForeignFn<($($a,)*)> for T
+        where
+            T: Fn($($a),*) -> R + 'static,
+            R: IntoSExp,
+            $($a: Arg),*
+        {
+            #[allow(unused_mut)]
+            fn arity() -> Result<Arity, RustString> {
+                let mut arity = Arity {
+                    required: 0,
+                    optional: 0,
+                    rest: false,
+                };
+                $(
+                    let arg = $a::arity();
+                    if arity.rest {
+                        return Err("a parameter follows the rest parameter".into());
+                    }
+                    if arity.optional > 0 && arg.required > 0 {
+                        return Err("a required parameter follows an optional one".into());
+                    }
+                    arity.required += arg.required;
+                    arity.optional += arg.optional;
+                    arity.rest |= arg.rest;
+                )*
+                Ok(arity)
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call<'a>(
+                &self,
+                context: &'a Context,
+                args: Vec<SExp<'a>>,
+            ) -> Result<SExp<'a>, RustString> {
+                let mut args = args.into_iter();
+                $(let $a = $a::take(&mut args)?;)*
+                Ok(self($($a),*).into_sexp(context))
+            }
+        }
+    };
+}
+
+foreign_fn!();
+foreign_fn!(A);
+foreign_fn!(A, B);
+foreign_fn!(A, B, C);
+foreign_fn!(A, B, C, D);
+foreign_fn!(A, B, C, D, E);
+foreign_fn!(A, B, C, D, E, F);
+
+type Foreign = dyn for<'a> Fn(&'a Context, sexp) -> Result<SExp<'a>, Exception<'a>>;
+
+// A closure registered with `define_fn`, and the srfi 69 procedures of the
+// context it was defined in, which its calls share.
+struct Registered {
+    f: Box<Foreign>,
+    srfi_69: rc::Rc<cell::Cell<Option<sexp>>>,
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> RustString {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => format!("panicked: {}", message),
+        None => match panic.downcast_ref::<RustString>() {
+            Some(message) => format!("panicked: {}", message),
+            None => RustString::from("panicked"),
+        },
+    }
+}
+
+// Every Rust procedure is registered with this function, taking all its
+// arguments as a rest list, and finds its closure in the opcode's data.
+unsafe extern "C" fn foreign_trampoline(ctx: sexp, op: sexp, _n: sexp_sint_t, args: sexp) -> sexp {
+    let registered = &*(sexp_cpointer_value(sexp_opcode_data(op)) as *const Registered);
+    // The context is borrowed from the caller for the length of the call.
+    let srfi_69 = registered.srfi_69.clone();
+    let mut context = mem::ManuallyDrop::new(Context(ctx, Default::default(), srfi_69));
+    // The closure catches panics itself; one that still escapes must not
+    // unwind into C.
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        match (registered.f)(&context, args) {
+            Ok(sexp) => sexp.sexp,
+            Err(e) => e.sexp,
+        }
+    }));
+    // Of the borrowed context, only the handle on the srfi 69 procedures is
+    // dropped.
+    ptr::drop_in_place(&mut context.2);
+    result.unwrap_or_else(|_| process::abort())
+}
+
+// An instance of a type from `define-record-type`. Its fields are the slots
+// its type names, in order.
+#[derive(SExp)]
+pub struct Record<'a>(RawSExp<'a>);
+
+impl<'a> Record<'a> {
+    pub fn record_type<'b>(&'b self) -> Type<'a> {
+        Type(RawSExp {
+            sexp: sexp_object_type(self.context.unwrap().0, self.sexp),
+            context: self.context,
+        })
+    }
+
+    pub fn type_name(&self) -> RustString {
+        self.record_type().name()
+    }
+
+    pub fn field_names(&self) -> Vec<RustString> {
+        self.record_type().field_names()
+    }
+
+    // Read from the type, which records its number of slots, without
+    // building the names.
+    pub fn len(&self) -> usize {
+        self.record_type().field_len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn field<'b>(&'b self, i: usize) -> Option<SExp<'a>> {
+        if i < self.len() {
+            let sexp = RawSExp {
+                sexp: sexp_slot_ref(self.sexp, i as _),
+                context: self.context,
+            };
+            Some(sexp.into())
+        } else {
+            None
+        }
+    }
+
+    pub fn field_by_name<'b>(&'b self, name: &str) -> Option<SExp<'a>> {
+        let i = self.field_names().iter().position(|field| field == name)?;
+        self.field(i)
+    }
+
+    // Panics if `i` is out of bounds, like `Vector::set`.
+    pub fn set_field(&mut self, i: usize, value: &SExp<'a>) {
+        assert!(i < self.len(), "index {} out of bounds for record of length {}", i, self.len());
+        sexp_slot_set(self.sexp, i as _, value.sexp)
+    }
+}
+
+impl<'a> fmt::Debug for Record<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_fmt(format_args!("#<{}", self.type_name()))?;
+        for (i, name) in self.field_names().iter().enumerate() {
+            fmt.write_fmt(format_args!(" ({} {:?})", name, self.field(i).unwrap()))?;
+        }
+        fmt.write_str(">")
+    }
+}
+
+// Scheme-defined types keep their field names in `slots`; the core types and
+// those defined in C have none, and neither do records without fields.
+fn sexp_recordp(context: &Context, sexp: sexp) -> bool {
+    if sexp_pointerp(sexp) && sexp_pointer_tag(sexp) >= sexp_types_SEXP_NUM_CORE_TYPES {
+        let slots = sexp_type_slots(sexp_object_type(context.0, sexp));
+        sexp_pairp(slots) || sexp_vectorp(slots)
+    } else {
+        false
+    }
+}
+
+// A srfi 69 hash table, which chibi defines as a record. Its operations are
+// the srfi 69 procedures, so they respect the table's own equivalence and
+// hash functions.
+#[derive(SExp)]
+pub struct HashTable<'a>(RawSExp<'a>);
+
+impl<'a> HashTable<'a> {
+    fn apply(&self, name: &str, args: &[sexp]) -> Result<SExp<'a>, Exception<'a>> {
+        let context = self.context.unwrap();
+        let procedure = context.srfi_69(name)?;
+        context.apply(procedure, &[&[self.sexp], args].concat())
+    }
+
+    pub fn len(&self) -> Result<usize, Exception<'a>> {
+        match self.apply("hash-table-size", &[])? {
+            SExp::Integer(i) => Ok(i64::from(&i) as usize),
+            o => Err(self.unexpected("hash-table-size", &o)),
+        }
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Exception<'a>> {
+        Ok(self.len()? == 0)
+    }
+
+    pub fn contains_key(&self, key: &SExp) -> Result<bool, Exception<'a>> {
+        match self.apply("hash-table-exists?", &[key.sexp])? {
+            SExp::Bool(b) => Ok(bool::from(&b)),
+            o => Err(self.unexpected("hash-table-exists?", &o)),
+        }
+    }
+
+    pub fn get(&self, key: &SExp) -> Result<Option<SExp<'a>>, Exception<'a>> {
+        if self.contains_key(key)? {
+            self.apply("hash-table-ref", &[key.sexp]).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn insert(&mut self, key: &SExp, value: &SExp) -> Result<(), Exception<'a>> {
+        self.apply("hash-table-set!", &[key.sexp, value.sexp])?;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &SExp) -> Result<Option<SExp<'a>>, Exception<'a>> {
+        let value = self.get(key)?;
+        self.apply("hash-table-delete!", &[key.sexp])?;
+        Ok(value)
+    }
+
+    // The entries as an association list, in no particular order.
+    pub fn to_alist(&self) -> Result<SExp<'a>, Exception<'a>> {
+        self.apply("hash-table->alist", &[])
+    }
+
+    // Iterates over a snapshot of the entries taken when it is called.
+    pub fn iter(&self) -> Result<HashTableIter<'a>, Exception<'a>> {
+        Ok(HashTableIter {
+            alist: self.to_alist()?,
+            context: self.context.unwrap(),
+        })
+    }
+
+    fn unexpected(&self, name: &str, result: &SExp) -> Exception<'a> {
+        self.context.unwrap().exception(&format!("{} returned {:?}", name, result))
+    }
+}
+
+pub struct HashTableIter<'a> {
+    alist: SExp<'a>,
+    context: &'a Context,
+}
+
+// Each entry, or an error for one `hash-table->alist` did not return as a
+// pair.
+impl<'a> Iterator for HashTableIter<'a> {
+    type Item = Result<(SExp<'a>, SExp<'a>), Exception<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (entry, rest) = match &self.alist {
+            SExp::Pair(pair) => (pair.car(), pair.cdr()),
+            _ => return None,
+        };
+        self.alist = rest;
+        match entry {
+            SExp::Pair(entry) => Some(Ok((entry.car(), entry.cdr()))),
+            o => {
+                let message = format!("hash-table->alist returned an entry {:?}", o);
+                Some(Err(self.context.exception(&message)))
+            }
+        }
+    }
+}
+
+// Symbol and string keys become strings; any other key fails the conversion.
+impl<'a> TryFrom<&HashTable<'a>> for HashMap<RustString, SExp<'a>> {
+    type Error = RustString;
+
+    fn try_from(table: &HashTable<'a>) -> Result<HashMap<RustString, SExp<'a>>, RustString> {
+        table
+            .iter()
+            .map_err(|e| format!("{:?}", e))?
+            .map(|entry| match entry.map_err(|e| format!("{:?}", e))? {
+                (SExp::Symbol(s), value) => Ok((RustString::from(&String::from(&s)), value)),
+                (SExp::String(s), value) => Ok((RustString::from(&s), value)),
+                (o, _) => Err(format!("{:?} is not a symbol or string", o)),
+            })
+            .collect()
+    }
+}
+
+impl<'a> fmt::Debug for HashTable<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str("#<hash-table")?;
+        for entry in self.iter().map_err(|_| fmt::Error)? {
+            let (key, value) = entry.map_err(|_| fmt::Error)?;
+            fmt.write_fmt(format_args!(" ({:?} . {:?})", key, value))?;
+        }
+        fmt.write_str(">")
+    }
+}
+
+fn sexp_hash_tablep(context: &Context, sexp: sexp) -> bool {
+    if !sexp_recordp(context, sexp) {
+        return false;
+    }
+    let name = sexp_object_type_name(context.0, sexp);
+    sexp_stringp(name) && RustString::from(&String(RawSExp::new(name))) == "Hash-Table"
+}
+
+// A type object, such as the one `define-record-type` binds to the type name.
+#[derive(SExp)]
+pub struct Type<'a>(RawSExp<'a>);
+
+impl<'a> Type<'a> {
+    pub fn name(&self) -> RustString {
+        let name = RawSExp {
+            sexp: sexp_type_name(self.sexp),
+            context: self.context,
+        };
+        match name.into() {
+            SExp::String(s) => RustString::from(&s),
+            SExp::Symbol(s) => RustString::from(&String::from(&s)),
+            o => format!("{:?}", o),
+        }
+    }
+
+    pub fn field_names(&self) -> Vec<RustString> {
+        let slots = RawSExp {
+            sexp: sexp_type_slots(self.sexp),
+            context: self.context,
+        };
+        let name = |slot: SExp| match slot {
+            SExp::Symbol(s) => RustString::from(&String::from(&s)),
+            o => format!("{:?}", o),
+        };
+        match slots.into() {
+            SExp::Pair(mut pair) => {
+                let mut names = vec![name(pair.car())];
+                while let SExp::Pair(next) = pair.cdr() {
+                    names.push(name(next.car()));
+                    pair = next;
+                }
+                names
+            }
+            SExp::Vector(vector) => vector.iter().map(name).collect(),
+            _ => vec![],
+        }
+    }
+
+    // The number of fields of its instances, counting those inherited from
+    // a parent type.
+    fn field_len(&self) -> usize {
+        sexp_type_field_len_base(self.sexp) as usize
+    }
+
+    // Builds an instance of a record type from its field values in order,
+    // as the constructor `define-record-type` generates does.
+    pub fn make(&self, fields: &[SExp]) -> Result<Record<'a>, Exception<'a>> {
+        let context = self.context.unwrap();
+        if self.field_len() != fields.len() {
+            return Err(context.exception(&format!(
+                "{} has {} fields but {} were given",
+                self.name(),
+                self.field_len(),
+                fields.len()
+            )));
+        }
+        // The type, its name, the constructor and the field values, which
+        // making the constructor and applying it cannot collect.
+        let fields = fields.iter().map(|field| field.sexp).collect::<Vec<_>>();
+        let roots = [self.sexp, SEXP_FALSE, SEXP_FALSE];
+        let roots = Roots::new(context, &[&roots[..], &fields[..]].concat());
+        roots.set(1, context.string(&self.name()).sexp);
+        roots.set(2, unsafe {
+            sexp_make_constructor_op(context.0, ptr::null_mut(), 2, roots.get(1), roots.get(0))
+        });
+        if sexp_exceptionp(roots.get(2)) {
+            return Err(Exception(RawSExp {
+                sexp: roots.get(2),
+                context: self.context,
+            }));
+        }
+        let sexp = RawSExp {
+            sexp: unsafe { sexp_apply(context.0, roots.get(2), SEXP_NULL) },
+            context: self.context,
+        };
+        if sexp_exceptionp(sexp.sexp) {
+            return Err(Exception(sexp));
+        }
+        for i in 0..fields.len() {
+            sexp_slot_set(sexp.sexp, i as _, roots.get(i + 3));
+        }
+        Ok(Record(sexp))
+    }
+}
+
+impl<'a> fmt::Debug for Type<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_fmt(format_args!("#<type {}>", self.name()))
+    }
+}
+
+#[derive(SExp)]
+pub struct Exception<'a>(RawSExp<'a>);
+
+impl Exception<'_> {
+    fn message(&self) -> String {
+        String(RawSExp {
+            sexp: sexp_exception_message(self.sexp),
+            context: self.context,
+        })
+    }
+}
+
+impl<'a> fmt::Debug for Exception<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_fmt(format_args!("error: {:?}", self.message()))
+    }
+}
+
+// An environment of global bindings, inheriting those of its parent. The
+// environments `Context` makes are preserved until the `Env` it returns is
+// dropped; any other `Env`, such as the one `Context::env` returns, leaves
+// the preservation alone.
+pub struct Env<'a>(RawSExp<'a>, bool);
+
+impl<'a> ops::Deref for Env<'a> {
+    type Target = RawSExp<'a>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for Env<'_> {
+    fn drop(&mut self) {
+        if let (true, Some(context)) = (self.1, self.context) {
+            unsafe { sexp_release_object(context.0, self.sexp) }
+        }
+    }
+}
+
+impl PartialEq for Env<'_> {
+    fn eq(&self, rhs: &Self) -> bool {
+        sexp_truep(sexp_equalp(self.context.unwrap().0, self.sexp, rhs.sexp))
+    }
+}
+
+impl<'a> From<Env<'a>> for SExp<'a> {
+    fn from(env: Env<'a>) -> SExp<'a> {
+        SExp::Env(env)
+    }
+}
+
+impl<'a> Env<'a> {
+    // Binds `name` in this environment, replacing any binding of its own but
+    // shadowing one it inherits.
+    pub fn define<V: IntoSExp>(&self, name: &str, value: V) -> Result<(), Exception<'a>> {
+        self.define_in(self.sexp, name, value)
+    }
+
+    // Changes the value of the binding of `name`, here or in the parent it
+    // is inherited from, like `set!`. Fails if there is none.
+    pub fn set<V: IntoSExp>(&self, name: &str, value: V) -> Result<(), Exception<'a>> {
+        let context = self.context.unwrap();
+        let env = match self.binding_env(name) {
+            Some(env) => env,
+            None => return Err(context.exception(&format!("{} is not defined", name))),
+        };
+        self.define_in(env, name, value)
+    }
+
+    fn define_in<V: IntoSExp>(&self, env: sexp, name: &str, value: V) -> Result<(), Exception<'a>> {
+        let context = self.context.unwrap();
+        // The environment, the value and the symbol, which interning and
+        // adding a binding cell cannot collect.
+        let roots = Roots::new(context, &[env, SEXP_FALSE, SEXP_FALSE]);
+        match value.into_sexp(context) {
+            SExp::Exception(e) => return Err(e),
+            value => roots.set(1, value.sexp),
+        }
+        roots.set(2, context.intern(name).sexp);
+        let sexp = RawSExp {
+            sexp: unsafe { sexp_env_define(context.0, roots.get(0), roots.get(2), roots.get(1)) },
+            context: self.context,
+        };
+        if sexp_exceptionp(sexp.sexp) {
+            Err(Exception(sexp))
+        } else {
+            Ok(())
+        }
+    }
+
+    // The value of `name`, here or inherited. Syntax has no value.
+    pub fn get(&self, name: &str) -> Option<SExp<'a>> {
+        let context = self.context.unwrap();
+        let symbol = context.intern(name);
+        let sexp = unsafe { sexp_env_ref(context.0, self.sexp, symbol.sexp, SEXP_UNDEF) };
+        if sexp == SEXP_UNDEF || sexp_syntaxp(sexp) {
+            None
+        } else {
+            Some(
+                RawSExp {
+                    sexp: sexp,
+                    context: self.context,
+                }
+                .into(),
+            )
+        }
+    }
+
+    // A new, empty environment inheriting this one's bindings. Definitions in
+    // it shadow the parent's rather than replacing them.
+    pub fn child(&self) -> Result<Env<'a>, Exception<'a>> {
+        let context = self.context.unwrap();
+        context.new_env(unsafe { sexp_extend_env(context.0, self.sexp, SEXP_NULL, SEXP_VOID) })
+    }
+
+    // Whether `name` is bound, here or inherited, to a value or to syntax.
+    pub fn contains(&self, name: &str) -> bool {
+        self.binding_env(name).is_some()
+    }
+
+    // The environment, this one or an ancestor, with its own binding of
+    // `name`.
+    fn binding_env(&self, name: &str) -> Option<sexp> {
+        let symbol = self.context.unwrap().intern(name);
+        let mut env = self.sexp;
+        while sexp_envp(env) {
+            let mut cell = sexp_env_bindings(env);
+            while sexp_pairp(cell) {
+                if sexp_car(cell) == symbol.sexp {
+                    return Some(env);
+                }
+                cell = sexp_env_next_cell(cell);
+            }
+            env = sexp_env_parent(env);
+        }
+        None
+    }
+
+    // The names and values of this environment's own bindings, most recently
+    // defined first, leaving out syntax and inherited bindings.
+    pub fn bindings(&self) -> EnvBindings<'a> {
+        EnvBindings {
+            cell: sexp_env_bindings(self.sexp),
+            context: self.context,
+        }
+    }
+}
+
+pub struct EnvBindings<'a> {
+    cell: sexp,
+    context: Option<&'a Context>,
+}
+
+impl<'a> Iterator for EnvBindings<'a> {
+    type Item = (RustString, SExp<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while sexp_pairp(self.cell) {
+            let cell = self.cell;
+            self.cell = sexp_env_next_cell(cell);
+            let (name, value) = (sexp_car(cell), sexp_cdr(cell));
+            if sexp_symbolp(name) && value != SEXP_UNDEF && !sexp_syntaxp(value) {
+                let name = Symbol(RawSExp {
+                    sexp: name,
+                    context: self.context,
+                });
+                let value = RawSExp {
+                    sexp: value,
+                    context: self.context,
+                };
+                return Some((RustString::from(&String::from(&name)), value.into()));
+            }
+        }
+        None
+    }
+}
+
+impl<'a> fmt::Debug for Env<'a> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.write_str("#<env ")?;
+        fmt.debug_map().entries(self.bindings()).finish()?;
+        fmt.write_str(">")
+    }
+}
+
+// Macros and core forms such as `if`.
+fn sexp_syntaxp(sexp: sexp) -> bool {
+    sexp_check_tag(sexp, sexp_types_SEXP_MACRO) || sexp_check_tag(sexp, sexp_types_SEXP_CORE)
+}
+
+#[derive(SExp)]
+pub struct Port<'a>(RawSExp<'a>);
+
+impl Port<'_> {
+    pub fn is_input(&self) -> bool {
+        sexp_iportp(self.sexp)
+    }
+
+    pub fn is_output(&self) -> bool {
+        sexp_oportp(self.sexp)
+    }
+
+    pub fn is_open(&self) -> bool {
+        sexp_port_openp(self.sexp)
+    }
+
+    pub fn flush(&self) {
+        sexp_flush_output(self.context.unwrap().0, self.sexp);
+    }
+
+    // Closing a port made from a Rust reader or writer drops it.
+    pub fn close(&mut self) {
+        sexp_close_port(self.context.unwrap().0, self.sexp);
+    }
+}
+
+impl fmt::Debug for Port<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match (self.is_input(), self.is_output()) {
+            (true, true) => fmt.write_str("#<port>"),
+            (true, false) => fmt.write_str("#<input-port>"),
+            _ => fmt.write_str("#<output-port>"),
+        }
+    }
+}
+
+// Any other object, such as syntax, bytecode or the end of file object, for
+// which there is no more specific variant.
+#[derive(SExp)]
+pub struct Opaque<'a>(RawSExp<'a>);
+
+impl Opaque<'_> {
+    // The name of its type, for objects allocated on the heap.
+    pub fn type_name(&self) -> Option<RustString> {
+        let context = self.context?;
+        if !sexp_pointerp(self.sexp) {
+            return None;
+        }
+        let object_type = Type(RawSExp {
+            sexp: sexp_object_type(context.0, self.sexp),
+            context: self.context,
+        });
+        Some(object_type.name())
+    }
+}
+
+impl fmt::Debug for Opaque<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self.type_name() {
+            Some(name) => fmt.write_fmt(format_args!("#<{}>", name)),
+            None => fmt.write_str("#<object>"),
+        }
+    }
+}
+
+// Owns the closures registered with `define_fn`, which live as long as it.
+// They are boxed so that the opcodes can point to them with a thin pointer.
+// The srfi 69 procedures are looked up once, see `srfi_69`, and shared with
+// the contexts the closures are called with.
+#[allow(clippy::vec_box)]
+pub struct Context(sexp, cell::RefCell<Vec<Box<Registered>>>, rc::Rc<cell::Cell<Option<sexp>>>);
+
+// The srfi 69 procedures `HashTable` uses.
+const SRFI_69: &[&str] = &[
+    "make-hash-table",
+    "hash-table-size",
+    "hash-table-exists?",
+    "hash-table-ref",
+    "hash-table-set!",
+    "hash-table-delete!",
+    "hash-table->alist",
+];
+
+impl<'a> From<RawSExp<'a>> for SExp<'a> {
+    //is the 'static lifetime not the bottom?
+    fn from(sexp: RawSExp<'a>) -> SExp<'a> {
+        if sexp_booleanp(sexp.sexp) {
+            if sexp_truep(sexp.sexp) {
+                TRUE.into()
+            } else {
+                FALSE.into()
+            }
+        } else if sexp.sexp == SEXP_VOID {
+            NULL.into()
+        } else if sexp_charp(sexp.sexp) {
+            Char(RawSExp::new(sexp.sexp)).into()
+        } else if sexp_nullp(sexp.sexp) {
+            NULL.into()
+        } else if sexp_fixnump(sexp.sexp) {
+            Integer(RawSExp::new(sexp.sexp)).into()
+        } else if sexp_bignump(sexp.sexp) {
+            BigInt(sexp).into()
+        } else if sexp_pairp(sexp.sexp) {
+            Pair(sexp).into()
+        } else if sexp_vectorp(sexp.sexp) {
+            Vector(sexp).into()
+        } else if sexp_bytesp(sexp.sexp) {
+            Bytevector(sexp).into()
+        } else if sexp_stringp(sexp.sexp) {
+            String(sexp).into()
+        } else if sexp_flonump(sexp.sexp) {
+            Rational(sexp).into()
+        } else if sexp_ratiop(sexp.sexp) {
+            Ratio(sexp).into()
+        } else if sexp_complexp(sexp.sexp) {
+            Complex(sexp).into()
+        } else if sexp_symbolp(sexp.sexp) {
+            Symbol(sexp).into()
+        } else if sexp_applicablep(sexp.sexp) {
+            Procedure(sexp).into()
+        } else if sexp_exceptionp(sexp.sexp) {
+            Exception(sexp).into()
+        } else if sexp_envp(sexp.sexp) {
+            Env(sexp, false).into()
+        } else if sexp_portp(sexp.sexp) {
+            Port(sexp).into()
+        } else if sexp_typep(sexp.sexp) {
+            Type(sexp).into()
+        } else if sexp.context.map_or(false, |context| sexp_hash_tablep(context, sexp.sexp)) {
+            HashTable(sexp).into()
+        } else if sexp.context.map_or(false, |context| sexp_recordp(context, sexp.sexp)) {
+            Record(sexp).into()
+        } else {
+            Opaque(sexp).into()
+        }
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        //TODO: switch to different default
+        Context(
+            unsafe {
+                sexp_make_eval_context(ptr::null_mut(), ptr::null_mut(), ptr::null_mut(), 0, 0)
+            },
+            Default::default(),
+            Default::default(),
+        )
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { sexp_destroy_context(self.0) };
+    }
+}
+
+impl Context {
+    pub fn eval_string(&self, str: &str) -> Result<SExp, Exception> {
+        self.eval(str, ptr::null_mut())
+    }
+
+    // Evaluates `str` in `env` instead of the context's environment.
+    pub fn eval_in<'a>(&'a self, env: &Env<'a>, str: &str) -> Result<SExp<'a>, Exception<'a>> {
+        self.eval(str, env.sexp)
+    }
+
+    // A null `env` is the context's environment.
+    fn eval(&self, str: &str, env: sexp) -> Result<SExp, Exception> {
+        let c_str = unsafe { ffi::CStr::from_bytes_with_nul_unchecked(str.as_bytes()) };
+        let sexp = RawSExp {
+            sexp: unsafe { sexp_eval_string(self.0, c_str.as_ptr(), str.len() as _, env) },
+            context: Some(self),
+        };
+        if sexp_exceptionp(sexp.sexp) {
+            Err(Exception(sexp).into())
+        } else {
+            Ok(sexp.into())
+        }
+    }
+
+    // A new environment with the standard library of the given R*RS
+    // `version`, that is `init-7.scm` and what it imports, for code that
+    // should not see the definitions of other code run in the context.
+    pub fn standard_env(&self, version: u8) -> Result<Env, Exception> {
+        #[cfg(feature = "embed-lib")]
+        self.add_embedded_lib()?;
+        self.new_env(sexp_make_standard_env(self.0, sexp_make_fixnum(version as _)))
+    }
+
+    // A new environment with only the core syntax, `define`, `lambda`, `if`
+    // and the like, of the given R*RS `version`.
+    pub fn null_env(&self, version: u8) -> Result<Env, Exception> {
+        self.new_env(sexp_make_null_env(self.0, sexp_make_fixnum(version as _)))
+    }
+
+    // A new environment with the core syntax and chibi's primitive procedures,
+    // but none of the standard library.
+    pub fn primitive_env(&self, version: u8) -> Result<Env, Exception> {
+        self.new_env(sexp_make_primitive_env(self.0, sexp_make_fixnum(version as _)))
+    }
+
+    // Nothing else refers to a new environment, so it is preserved until the
+    // `Env` is dropped.
+    fn new_env(&self, sexp: sexp) -> Result<Env, Exception> {
+        let sexp = RawSExp {
+            sexp: sexp,
+            context: Some(self),
+        };
+        if sexp_exceptionp(sexp.sexp) {
+            return Err(Exception(sexp));
+        }
+        let roots = Roots::new(self, &[sexp.sexp]);
+        unsafe { sexp_preserve_object(self.0, roots.get(0)) };
+        Ok(Env(sexp, true))
+    }
+
+    // Serves `init-7.scm` and the standard modules from the copy compiled into
+    // the binary rather than from the install prefix, writing it out the
+    // first time a context needs it, see `embed`.
+    #[cfg(feature = "embed-lib")]
+    fn add_embedded_lib(&self) -> Result<(), Exception> {
+        let module_dir = crate::embed::module_dir();
+        let dir = module_dir.to_string_lossy();
+        // Only once per context, however often a standard environment is
+        // made.
+        let mut path = sexp_global(self.0, sexp_context_globals_SEXP_G_MODULE_PATH);
+        while sexp_pairp(path) {
+            let entry = sexp_car(path);
+            if sexp_stringp(entry) {
+                let len = sexp_string_size(entry) as usize;
+                let data: &[u8] =
+                    unsafe { slice::from_raw_parts(sexp_string_data(entry) as _, len) };
+                if data == dir.as_bytes() {
+                    return Ok(());
+                }
+            }
+            path = sexp_cdr(path);
+        }
+        crate::embed::extract(&module_dir).map_err(|e| self.exception(&e))?;
+        let dir = self.string(&dir);
+        sexp_add_module_directory(self.0, dir.sexp, SEXP_FALSE);
+        Ok(())
+    }
+
+    fn exception(&self, message: &str) -> Exception {
+        // A message from Rust may hold NULs, which C strings cannot.
+        let message = ffi::CString::new(message.replace('\0', "\\0")).unwrap();
+        Exception(RawSExp {
+            sexp: unsafe {
+                sexp_user_exception(self.0, ptr::null_mut(), message.as_ptr(), SEXP_NULL)
+            },
+            context: Some(self),
+        })
+    }
+
+    pub fn cons<'a>(&self, a: &'a SExp, b: &'a SExp) -> SExp {
+        let roots = Roots::new(self, &[a.sexp, b.sexp]);
+        let sexp = RawSExp {
+            sexp: sexp_cons(self.0, roots.get(0), roots.get(1)),
+            context: Some(self),
+        };
+        if !sexp_exceptionp(sexp.sexp) {
+            Pair(sexp).into()
+        } else {
+            Exception(sexp).into()
+        }
+    }
+
+    pub fn vector(&self, values: &[SExp]) -> Vector {
+        // The vector and then the values, which making it cannot collect.
+        let values = values.iter().map(|value| value.sexp).collect::<Vec<_>>();
+        let roots = Roots::new(self, &[&[SEXP_VOID], &values[..]].concat());
+        roots.set(0, sexp_make_vector(self.0, sexp_make_fixnum(values.len() as _), SEXP_VOID));
+        for i in 0..values.len() {
+            sexp_vector_set(roots.get(0), sexp_make_fixnum(i as _), roots.get(i + 1));
+        }
+        Vector(RawSExp {
+            sexp: roots.get(0),
+            context: Some(self),
+        })
+    }
+
+    pub fn bytevector(&self, bytes: &[u8]) -> Bytevector {
+        let sexp = sexp_make_bytes(self.0, sexp_make_fixnum(bytes.len() as _), sexp_make_fixnum(0));
+        let mut bytevector = Bytevector(RawSExp {
+            sexp: sexp,
+            context: Some(self),
+        });
+        bytevector.data_mut().copy_from_slice(bytes);
+        bytevector
+    }
+
+    // Parses a decimal integer of any size.
+    pub fn parse_integer(&self, str: &str) -> Result<SExp, NumberError> {
+        // Parsing a bignum allocates.
+        let roots = Roots::new(self, &[self.string(str).sexp]);
+        let sexp = RawSExp {
+            sexp: sexp_string_to_number(self.0, roots.get(0), sexp_make_fixnum(10)),
+            context: Some(self),
+        };
+        if sexp_exact_integerp(sexp.sexp) {
+            Ok(sexp.into())
+        } else {
+            Err(NumberError::InvalidInteger(RustString::from(str)))
+        }
+    }
+
+    #[cfg(feature = "bignums")]
+    pub fn integer(&self, i: i128) -> SExp {
+        self.parse_integer(&i.to_string()).unwrap()
+    }
+
+    #[cfg(feature = "bignums")]
+    pub fn unsigned_integer(&self, u: u128) -> SExp {
+        self.parse_integer(&u.to_string()).unwrap()
+    }
+
+    #[cfg(all(feature = "bignums", feature = "num-bigint"))]
+    pub fn bigint(&self, b: &num_bigint::BigInt) -> SExp {
+        self.parse_integer(&b.to_str_radix(10)).unwrap()
+    }
+
+    // The nearest flonum to a real number.
+    fn to_f64(&self, sexp: sexp) -> f64 {
+        sexp_flonum_value(unsafe { sexp_exact_to_inexact(self.0, ptr::null_mut(), 1, sexp) })
+    }
+
+    // A list of `values`, or the first exception converting them gives.
+    fn list<'a, T: IntoSExp>(&'a self, values: impl ExactSizeIterator<Item = T>) -> SExp<'a> {
+        // Each value is rooted as soon as it is converted, so that converting
+        // the next cannot collect it.
+        let roots = Roots::new(self, &vec![SEXP_NULL; values.len() + 1]);
+        for (i, value) in values.enumerate() {
+            if let Err(e) = roots.convert(self, i + 1, value) {
+                return e;
+            }
+        }
+        self.list_of(roots)
+    }
+
+    // A list of the values in all but the first of `roots`, which holds the
+    // list as it is built.
+    fn list_of(&self, roots: Roots) -> SExp {
+        for i in (1..roots.vars.len()).rev() {
+            roots.set(0, sexp_cons(self.0, roots.get(i), roots.get(0)));
+        }
+        RawSExp {
+            sexp: roots.get(0),
+            context: Some(self),
+        }
+        .into()
+    }
+
+    // Builds an `equal?` hash table keyed by symbols.
+    pub fn hash_table<'a>(
+        &'a self,
+        map: &HashMap<RustString, SExp<'a>>,
+    ) -> Result<HashTable<'a>, Exception<'a>> {
+        let table = match self.apply(self.srfi_69("make-hash-table")?, &[])? {
+            SExp::HashTable(table) => table,
+            o => return Err(self.exception(&format!("make-hash-table returned {:?}", o))),
+        };
+        // The table and each key, which interning the next and inserting
+        // cannot collect.
+        let roots = Roots::new(self, &[table.sexp, SEXP_FALSE]);
+        for (key, value) in map {
+            roots.set(1, self.intern(key).sexp);
+            table.apply("hash-table-set!", &[roots.get(1), value.sexp])?;
+        }
+        Ok(table)
+    }
+
+    // A srfi 69 procedure, looked up in its own module so that the table
+    // operations work wherever the table came from. The procedures are looked
+    // up together the first time one is needed and kept in a vector preserved
+    // for as long as the context, rather than preserved themselves, which
+    // dropping any wrapper of them would undo. The module is loaded from the
+    // meta environment, which has `environment` whatever the context's own
+    // environment is.
+    fn srfi_69(&self, name: &str) -> Result<sexp, Exception> {
+        let i = SRFI_69.iter().position(|&n| n == name).unwrap();
+        if let Some(procedures) = self.2.get() {
+            return Ok(sexp_vector_ref(procedures, sexp_make_fixnum(i as _)));
+        }
+        let meta_env = sexp_global(self.0, sexp_context_globals_SEXP_G_META_ENV);
+        if !sexp_envp(meta_env) {
+            return Err(self.exception("srfi 69 needs a standard environment to be loaded"));
+        }
+        let env = match self.eval("(environment '(srfi 69))", meta_env)? {
+            SExp::Env(env) => env,
+            o => return Err(self.exception(&format!("srfi 69 is unavailable: {:?}", o))),
+        };
+        let roots = Roots::new(self, &[env.sexp, SEXP_FALSE]);
+        let len = sexp_make_fixnum(SRFI_69.len() as _);
+        roots.set(1, sexp_make_vector(self.0, len, SEXP_FALSE));
+        for (j, name) in SRFI_69.iter().enumerate() {
+            let symbol = self.intern(name);
+            let procedure = unsafe { sexp_env_ref(self.0, roots.get(0), symbol.sexp, SEXP_FALSE) };
+            if !sexp_applicablep(procedure) {
+                return Err(self.exception(&format!("srfi 69 does not define {}", name)));
+            }
+            sexp_vector_set(roots.get(1), sexp_make_fixnum(j as _), procedure);
+        }
+        let procedures = roots.get(1);
+        unsafe { sexp_preserve_object(self.0, procedures) };
+        self.2.set(Some(procedures));
+        Ok(sexp_vector_ref(procedures, sexp_make_fixnum(i as _)))
+    }
+
+    // Applies `procedure` to `args`, keeping both from the collector while
+    // the argument list is built.
+    fn apply<'a>(&'a self, procedure: sexp, args: &[sexp]) -> Result<SExp<'a>, Exception<'a>> {
+        // The procedure, the list of arguments built so far and the arguments
+        // still to be consed onto it.
+        let roots = Roots::new(self, &[&[procedure, SEXP_NULL], args].concat());
+        for i in (2..2 + args.len()).rev() {
+            roots.set(1, sexp_cons(self.0, roots.get(i), roots.get(1)));
+        }
+        let sexp = RawSExp {
+            sexp: unsafe { sexp_apply(self.0, roots.get(0), roots.get(1)) },
+            context: Some(self),
+        };
+        if sexp_exceptionp(sexp.sexp) {
+            Err(Exception(sexp))
+        } else {
+            Ok(sexp.into())
+        }
+    }
+
+    // The value bound to `name` in the context's environment.
+    pub fn lookup(&self, name: &str) -> Option<SExp> {
+        self.env().get(name)
+    }
+
+    // The environment code is evaluated in.
+    pub fn env(&self) -> Env {
+        Env(
+            RawSExp {
+                sexp: sexp_context_env(self.0),
+                context: Some(self),
+            },
+            false,
+        )
+    }
+
+    // Makes `env` the environment `eval_string`, `define_fn`, `lookup` and
+    // `call` use.
+    pub fn set_env(&self, env: &Env) {
+        sexp_context_env_set(self.0, env.sexp);
+    }
+
+    // Applies the procedure bound to `name` to `args`, a tuple or `Vec` of
+    // values, or `()` for none. The arguments are passed as values, so unlike
+    // code given to `eval_string` they are never read as Scheme.
+    pub fn call<A: IntoSExp>(&self, name: &str, args: A) -> Result<SExp, Exception> {
+        let procedure = match self.lookup(name) {
+            Some(SExp::Procedure(procedure)) => procedure,
+            Some(o) => return Err(self.exception(&format!("{} is not a procedure: {:?}", name, o))),
+            None => return Err(self.exception(&format!("{} is not defined", name))),
+        };
+        let args = match args.into_sexp(self) {
+            SExp::Void(_) | SExp::Null(_) => SEXP_NULL,
+            SExp::Pair(ref list) if list.is_list() => list.sexp,
+            SExp::Exception(e) => return Err(e),
+            o => return Err(self.exception(&format!("expected a list of arguments, got {:?}", o))),
+        };
+        let sexp = RawSExp {
+            sexp: unsafe { sexp_apply(self.0, procedure.sexp, args) },
+            context: Some(self),
+        };
+        if sexp_exceptionp(sexp.sexp) {
+            Err(Exception(sexp))
+        } else {
+            Ok(sexp.into())
+        }
+    }
+
+    // Binds `name` in the context's environment to a procedure calling `f`.
+    // Arguments are converted with `FromSExp` and the result with `IntoSExp`,
+    // and a failed conversion, a wrong number of arguments or a panic in `f`
+    // raises a Scheme error.
+    pub fn define_fn<F: ForeignFn<Args>, Args>(&self, name: &str, f: F) -> Result<(), Exception> {
+        let c_name = match ffi::CString::new(name) {
+            Ok(c_name) => c_name,
+            Err(_) => return Err(self.exception(&format!("{:?} contains a NUL byte", name))),
+        };
+        let arity = match F::arity() {
+            Ok(arity) => arity,
+            Err(e) => return Err(self.exception(&format!("{}: {}", name, e))),
+        };
+        let owned_name = RustString::from(name);
+        let closure: Box<Foreign> = Box::new(move |context, mut list| {
+            let call = || {
+                let mut args = Vec::new();
+                while sexp_pairp(list) {
+                    args.push(
+                        RawSExp {
+                            sexp: sexp_car(list),
+                            context: Some(context),
+                        }
+                        .into(),
+                    );
+                    list = sexp_cdr(list);
+                }
+                let result = if arity.accepts(args.len()) {
+                    f.call(context, args)
+                } else {
+                    Err(format!("wrong number of arguments: {}", args.len()))
+                };
+                result.map_err(|e| context.exception(&format!("{}: {}", owned_name, e)))
+            };
+            panic::catch_unwind(panic::AssertUnwindSafe(call)).unwrap_or_else(|panic| {
+                let message = panic_message(&*panic);
+                Err(context.exception(&format!("{}: {}", owned_name, message)))
+            })
+        });
+        let closure = Box::new(Registered {
+            f: closure,
+            srfi_69: self.2.clone(),
+        });
+        let data = unsafe {
+            sexp_make_cpointer(
+                self.0,
+                sexp_types_SEXP_CPOINTER as sexp_uint_t,
+                &*closure as *const Registered as *mut raw::c_void,
+                SEXP_FALSE,
+                0,
+            )
+        };
+        self.1.borrow_mut().push(closure);
+        // Kept from the collector until the opcode refers to it.
+        unsafe { sexp_preserve_object(self.0, data) };
+        let sexp = RawSExp {
+            sexp: unsafe {
+                sexp_define_foreign_proc_aux(
+                    self.0,
+                    sexp_context_env(self.0),
+                    c_name.as_ptr(),
+                    1,
+                    SEXP_PROC_VARIADIC as raw::c_int,
+                    c_name.as_ptr(),
+                    mem::transmute::<sexp_proc2, sexp_proc1>(Some(foreign_trampoline)),
+                    data,
+                )
+            },
+            context: Some(self),
+        };
+        unsafe { sexp_release_object(self.0, data) };
+        if sexp_exceptionp(sexp.sexp) {
+            Err(Exception(sexp))
+        } else {
+            Ok(())
+        }
+    }
+
+    // An input port reading from `r`, which is dropped when the port is closed
+    // or collected.
+    pub fn input_port<R: io::Read + 'static>(&self, r: R) -> Result<Port, Exception> {
+        self.file_port(crate::cookie::reader(r), sexp_make_input_port)
+    }
+
+    // An unbuffered output port writing to `w`, which is flushed and dropped
+    // when the port is closed or collected.
+    pub fn output_port<W: io::Write + 'static>(&self, w: W) -> Result<Port, Exception> {
+        self.file_port(crate::cookie::writer(w), sexp_make_output_port)
+    }
+
+    fn file_port(
+        &self,
+        stream: *mut FILE,
+        make: unsafe extern "C" fn(sexp, *mut FILE, sexp) -> sexp,
+    ) -> Result<Port, Exception> {
+        if stream.is_null() {
+            return Err(self.exception("custom ports are not supported on this platform"));
+        }
+        let sexp = RawSExp {
+            sexp: unsafe { make(self.0, stream, SEXP_FALSE) },
+            context: Some(self),
+        };
+        match sexp.into() {
+            SExp::Port(port) => Ok(port),
+            o => {
+                crate::cookie::discard(stream);
+                match o {
+                    SExp::Exception(e) => Err(e),
+                    o => Err(self.exception(&format!("expected a port, got {:?}", o))),
+                }
+            }
+        }
+    }
+
+    pub fn set_current_input_port(&self, port: &Port) {
+        self.set_parameter(sexp_context_globals_SEXP_G_CUR_IN_SYMBOL, port);
+    }
+
+    pub fn set_current_output_port(&self, port: &Port) {
+        self.set_parameter(sexp_context_globals_SEXP_G_CUR_OUT_SYMBOL, port);
+    }
+
+    pub fn set_current_error_port(&self, port: &Port) {
+        self.set_parameter(sexp_context_globals_SEXP_G_CUR_ERR_SYMBOL, port);
+    }
+
+    fn set_parameter(&self, name: sexp_context_globals, port: &Port) {
+        let env = sexp_context_env(self.0);
+        unsafe { sexp_set_parameter(self.0, env, sexp_global(self.0, name), port.sexp) };
+    }
+
+    // The external representation of `sexp`, as `write` would print it.
+    fn write_string(&self, sexp: sexp) -> RustString {
+        let roots = Roots::new(self, &[sexp, sexp_open_output_string(self.0)]);
+        sexp_write(self.0, roots.get(0), roots.get(1));
+        let string = String(RawSExp {
+            sexp: sexp_get_output_string(self.0, roots.get(1)),
+            context: Some(self),
+        });
+        RustString::from(&string)
+    }
+
+    pub fn flonum(&self, i: f64) -> Rational {
+        let sexp = unsafe { sexp_make_flonum(self.0, i) };
+        Rational(RawSExp {
+            sexp: sexp,
+            context: Some(self),
+        })
+    }
+
+    pub fn string(&self, str: &str) -> String {
+        let len = str.len();
+        let c_str = unsafe { ffi::CStr::from_bytes_with_nul_unchecked(str.as_bytes()) };
+        let sexp = unsafe { sexp_c_string(self.0, c_str.as_ptr(), len as _) };
+        String(RawSExp {
+            sexp: sexp,
+            context: Some(self),
+        })
+    }
+
+    pub fn intern(&self, str: &str) -> Symbol {
+        let len = str.len();
+        let c_str = unsafe { ffi::CStr::from_bytes_with_nul_unchecked(str.as_bytes()) };
+        let sexp = unsafe { sexp_intern(self.0, c_str.as_ptr(), len as _) };
+        Symbol(RawSExp {
+            sexp: sexp,
+            context: Some(self),
+        })
+    }
+}
+
+mod tests {
+
+    use crate::sexp::*;
+
+    #[test]
+    fn test_pair() {
+        let context = Context::default();
+        assert_eq!(
+            Ok(context.cons(&Char::from('c' as raw::c_char).into(), &FALSE.into())),
+            context.eval_string("'(#\\c . #f)")
         );
 
         assert_eq!(
@@ -490,6 +2721,263 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_vector() {
+        let context = Context::default();
+        let one = Integer::from(1).into();
+        let two = Integer::from(2).into();
+        let three = Integer::from(3).into();
+        assert_eq!(
+            Ok(context.vector(&[one, two, three]).into()),
+            context.eval_string("#(1 2 3)")
+        );
+
+        assert_eq!(
+            "#(1 #t (1 . 2) #())",
+            format!("{:?}", context.eval_string("#(1 #t (1 . 2) #())").unwrap())
+        );
+
+        let mut vector = match context.eval_string("(vector 1 2 3)") {
+            Ok(SExp::Vector(vector)) => vector,
+            o => panic!("expected a vector, got {:?}", o),
+        };
+        assert_eq!(3, vector.len());
+        assert_eq!(SExp::from(Integer::from(2)), vector.get(1));
+        vector.set(1, &TRUE.into());
+        assert_eq!(
+            vec![SExp::from(Integer::from(1)), TRUE.into(), Integer::from(3).into()],
+            vector.iter().collect::<Vec<SExp>>()
+        );
+    }
+
+    #[test]
+    fn test_bytevector() {
+        let context = Context::default();
+        assert_eq!(
+            Ok(context.bytevector(&[1, 2, 255]).into()),
+            context.eval_string("#u8(1 2 255)")
+        );
+
+        assert_eq!(
+            "#u8(1 2 255)",
+            format!("{:?}", context.eval_string("(bytevector 1 2 255)").unwrap())
+        );
+
+        let mut bytevector = match context.eval_string("(make-bytevector 3 7)") {
+            Ok(SExp::Bytevector(bytevector)) => bytevector,
+            o => panic!("expected a bytevector, got {:?}", o),
+        };
+        assert_eq!(&[7, 7, 7], bytevector.data());
+        bytevector.data_mut()[1] = 0;
+        assert_eq!(vec![7, 0, 7], Vec::from(&bytevector));
+        assert!(context.bytevector(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_procedure() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+
+        let add = match context.eval_string("(lambda (x y . z) (apply + x y z))") {
+            Ok(SExp::Procedure(add)) => add,
+            o => panic!("expected a procedure, got {:?}", o),
+        };
+        assert_eq!(ProcedureKind::Closure, add.kind());
+        assert_eq!(
+            Arity {
+                required: 2,
+                optional: 0,
+                rest: true,
+            },
+            add.arity()
+        );
+        assert_eq!(None, add.name());
+        let args = [Integer::from(1).into(), Integer::from(2).into(), Integer::from(3).into()];
+        for _ in 0..3 {
+            assert_eq!(Ok(Integer::from(6).into()), add.call(&args));
+        }
+        assert!(add.call(&[]).is_err());
+
+        context.eval_string("(define (twice x) (* x 2))").unwrap();
+        let twice = match context.eval_string("twice") {
+            Ok(SExp::Procedure(twice)) => twice,
+            o => panic!("expected a procedure, got {:?}", o),
+        };
+        assert_eq!(Some("twice".to_string()), twice.name());
+        assert_eq!("#<procedure twice>", format!("{:?}", twice));
+        assert!(twice.arity().accepts(1));
+        assert!(!twice.arity().accepts(2));
+
+        let car = match context.eval_string("car") {
+            Ok(SExp::Procedure(car)) => car,
+            o => panic!("expected a procedure, got {:?}", o),
+        };
+        assert_eq!(ProcedureKind::Opcode, car.kind());
+        assert_eq!(Some("car".to_string()), car.name());
+        assert_eq!(
+            Ok(Integer::from(1).into()),
+            car.call(&[context.eval_string("'(1 2)").unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_record() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context
+            .eval_string(
+                "(define-record-type point (make-point x y) point? \
+                 (x point-x) (y point-y set-point-y!))",
+            )
+            .unwrap();
+
+        let mut point = match context.eval_string("(make-point 1 \"two\")") {
+            Ok(SExp::Record(point)) => point,
+            o => panic!("expected a record, got {:?}", o),
+        };
+        assert_eq!("point", point.type_name());
+        assert_eq!(vec!["x".to_string(), "y".to_string()], point.field_names());
+        assert_eq!(2, point.len());
+        assert_eq!(Some(Integer::from(1).into()), point.field(0));
+        assert_eq!(Some(context.string("two").into()), point.field_by_name("y"));
+        assert_eq!(None, point.field(2));
+        assert_eq!(None, point.field_by_name("z"));
+        assert_eq!("#<point (x 1) (y \"two\")>", format!("{:?}", point));
+
+        point.set_field(0, &Integer::from(3).into());
+        assert_eq!(Some(Integer::from(3).into()), point.field_by_name("x"));
+
+        let point_type = match context.eval_string("point") {
+            Ok(SExp::Type(point_type)) => point_type,
+            o => panic!("expected a type, got {:?}", o),
+        };
+        let made = point_type
+            .make(&[Integer::from(4).into(), Integer::from(5).into()])
+            .unwrap();
+        assert_eq!(Some(Integer::from(5).into()), made.field_by_name("y"));
+        assert!(point_type.make(&[Integer::from(4).into()]).is_err());
+
+        let point_y = match context.eval_string("point-y") {
+            Ok(SExp::Procedure(point_y)) => point_y,
+            o => panic!("expected a procedure, got {:?}", o),
+        };
+        assert_eq!(Ok(Integer::from(5).into()), point_y.call(&[made.into()]));
+    }
+
+    #[test]
+    fn test_record_inherited_fields() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context
+            .eval_string(
+                "(define-record-type point (make-point x y) point? (x point-x) (y point-y)) \
+                 (define-record-type (point3 point) (make-point3 x y z) point3? (z point3-z))",
+            )
+            .unwrap();
+
+        let point3_type = match context.eval_string("point3") {
+            Ok(SExp::Type(point3_type)) => point3_type,
+            o => panic!("expected a type, got {:?}", o),
+        };
+        let made = point3_type
+            .make(&[Integer::from(1).into(), Integer::from(2).into(), Integer::from(3).into()])
+            .unwrap();
+        assert_eq!(3, made.len());
+        assert_eq!(Some(Integer::from(3).into()), made.field(2));
+        assert!(point3_type.make(&[Integer::from(1).into()]).is_err());
+
+        let point3_z = match context.eval_string("point3-z") {
+            Ok(SExp::Procedure(point3_z)) => point3_z,
+            o => panic!("expected a procedure, got {:?}", o),
+        };
+        assert_eq!(Ok(Integer::from(3).into()), point3_z.call(&[made.into()]));
+    }
+
+    #[test]
+    fn test_hash_table() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.eval_string("(import (srfi 69))").unwrap();
+
+        let mut table = match context
+            .eval_string("(let ((t (make-hash-table))) (hash-table-set! t 'a 1) t)")
+        {
+            Ok(SExp::HashTable(table)) => table,
+            o => panic!("expected a hash table, got {:?}", o),
+        };
+        let a = SExp::from(context.intern("a"));
+        let b = SExp::from(context.string("b"));
+        assert_eq!(Ok(1), table.len());
+        assert_eq!(Ok(Some(Integer::from(1).into())), table.get(&a));
+        assert_eq!(Ok(None), table.get(&b));
+
+        table.insert(&b, &TRUE.into()).unwrap();
+        assert_eq!(Ok(2), table.len());
+        assert_eq!(Ok(Some(TRUE.into())), table.get(&context.string("b").into()));
+
+        let map = HashMap::try_from(&table).unwrap();
+        assert_eq!(2, map.len());
+        assert_eq!(Some(&SExp::from(Integer::from(1))), map.get("a"));
+        assert_eq!(Some(&SExp::from(TRUE)), map.get("b"));
+
+        assert_eq!(Ok(Some(Integer::from(1).into())), table.remove(&a));
+        assert_eq!(Ok(None), table.remove(&a));
+        assert_eq!(
+            Ok(vec![(b, TRUE.into())]),
+            table.iter().unwrap().collect::<Result<Vec<(SExp, SExp)>, _>>()
+        );
+
+        table.insert(&Integer::from(1).into(), &FALSE.into()).unwrap();
+        assert!(HashMap::try_from(&table).is_err());
+
+        let mut map = HashMap::new();
+        map.insert("x".to_string(), SExp::from(Integer::from(2)));
+        let table = context.hash_table(&map).unwrap();
+        assert_eq!(Ok(Some(Integer::from(2).into())), table.get(&context.intern("x").into()));
+    }
+
+    #[test]
+    fn test_hash_table_in_foreign_fn() {
+        let context = Context::default();
+        // Loading a standard environment makes the meta environment.
+        context.standard_env(7).unwrap();
+        let primitive = context.primitive_env(7).unwrap();
+        context.set_env(&primitive);
+        context
+            .define_fn("make-table", || {
+                let mut map = HashMap::new();
+                map.insert("a".to_string(), 1);
+                map
+            })
+            .unwrap();
+        let table = match context.eval_string("(make-table)") {
+            Ok(SExp::HashTable(table)) => table,
+            o => panic!("expected a hash table, got {:?}", o),
+        };
+        // Looked up during the call, in the meta environment rather than the
+        // primitive one, and kept for the context.
+        assert!(context.2.get().is_some());
+        assert_eq!(Ok(Some(Integer::from(1).into())), table.get(&context.string("a").into()));
+    }
+
+    #[test]
+    fn test_hash_table_without_srfi_69() {
+        let context = Context::default();
+        assert!(context.hash_table(&HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_opaque() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        let eof = match context.eval_string("(eof-object)") {
+            Ok(SExp::Opaque(eof)) => eof,
+            o => panic!("expected an opaque object, got {:?}", o),
+        };
+        assert_eq!(None, eof.type_name());
+        assert_eq!("#<object>", format!("{:?}", eof));
+    }
+
     #[test]
     fn test_null() {
         let context = Context::default();
@@ -512,6 +3000,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_unicode_char() {
+        let context = Context::default();
+        assert_eq!(context.eval_string("#\\x3bb"), Ok(Char::from('λ').into()));
+        assert_eq!(context.eval_string("#\\λ"), Ok(Char::from('λ').into()));
+        assert_eq!(
+            context.eval_string("(integer->char #x1f600)"),
+            Ok(Char::from('😀').into())
+        );
+        assert_eq!(Ok('😀'), char::try_from(&Char::from('😀')));
+        assert_eq!(0x3bb, Char::from('λ').code_point());
+
+        assert_eq!("#\\space", format!("{:?}", Char::from(' ')));
+        assert_eq!("#\\newline", format!("{:?}", Char::from('\n')));
+        assert_eq!("#\\x3bb", format!("{:?}", Char::from('λ')));
+        assert_eq!("#\\x1", format!("{:?}", Char::from('\u{1}')));
+        assert_eq!("#\\a", format!("{:?}", Char::from('a')));
+        assert_eq!("#\\)", format!("{:?}", Char::from(')')));
+    }
+
     #[test]
     fn test_integer() {
         let context = Context::default();
@@ -528,6 +3036,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bigint() {
+        let context = Context::default();
+        let big = match context.eval_string("(expt 2 100)") {
+            Ok(SExp::BigInt(big)) => big,
+            o => panic!("expected a bignum, got {:?}", o),
+        };
+        assert_eq!(Ok(1 << 100), i128::try_from(&big));
+        assert_eq!(Ok(1 << 100), u128::try_from(&big));
+        assert_eq!(
+            Err(NumberError::OutOfRange {
+                value: "1267650600228229401496703205376".to_string(),
+                target: "i64",
+            }),
+            i64::try_from(&big)
+        );
+        assert_eq!("1267650600228229401496703205376", format!("{:?}", big));
+
+        assert_eq!(
+            context.eval_string("(- (expt 2 127))"),
+            Ok(context.integer(i128::min_value()))
+        );
+        assert_eq!(
+            context.eval_string("(- (expt 2 128) 1)"),
+            Ok(context.unsigned_integer(u128::max_value()))
+        );
+        assert_eq!(Ok(Integer::from(5).into()), context.parse_integer("5"));
+        assert_eq!(SExp::from(Integer::from(-5)), context.integer(-5));
+
+        match context.parse_integer("-18446744073709551616") {
+            Ok(SExp::BigInt(big)) => {
+                assert_eq!(Ok(-(1 << 64)), i128::try_from(&big));
+                assert!(u128::try_from(&big).is_err());
+                assert!(u64::try_from(&big).is_err());
+            }
+            o => panic!("expected a bignum, got {:?}", o),
+        }
+
+        match context.eval_string("(expt 2 128)") {
+            Ok(SExp::BigInt(big)) => assert!(u128::try_from(&big).is_err()),
+            o => panic!("expected a bignum, got {:?}", o),
+        }
+
+        assert_eq!(
+            Err(NumberError::InvalidInteger("1.5".to_string())),
+            context.parse_integer("1.5")
+        );
+    }
+
+    #[test]
+    fn test_number() {
+        let context = Context::default();
+        let minus_one = context.eval_string("-1").unwrap();
+        let minus_one = Number::try_from(&minus_one).unwrap();
+        assert_eq!(Ok(-1), i8::try_from(minus_one));
+        assert_eq!(Ok(-1.0), f32::try_from(minus_one));
+        assert_eq!(
+            Err(NumberError::OutOfRange {
+                value: "-1".to_string(),
+                target: "u32",
+            }),
+            u32::try_from(minus_one)
+        );
+
+        let big = context.eval_string("(expt 2 64)").unwrap();
+        let big = Number::try_from(&big).unwrap();
+        assert_eq!(Ok(1 << 64), u128::try_from(big));
+        assert_eq!(Ok(2f64.powi(64)), f64::try_from(big));
+        assert!(u64::try_from(big).is_err());
+        assert!(usize::try_from(big).is_err());
+
+        let half = context.eval_string("1/2").unwrap();
+        let half = Number::try_from(&half).unwrap();
+        assert_eq!(Ok(0.5), f64::try_from(half));
+        assert_eq!(
+            Err(NumberError::NotAnInteger("1/2".to_string())),
+            i64::try_from(half)
+        );
+
+        let flonum = context.flonum(1e300).into();
+        let flonum = Number::try_from(&flonum).unwrap();
+        assert!(i64::try_from(flonum).is_err());
+        assert!(f32::try_from(flonum).is_err());
+        assert_eq!(Ok(1e300), f64::try_from(flonum));
+
+        let string = context.string("1").into();
+        assert!(Number::try_from(&string).is_err());
+    }
+
     #[test]
     fn test_rational() {
         let context = Context::default();
@@ -535,6 +3132,47 @@ mod tests {
         assert_eq!(context.eval_string("4.5"), Ok(context.flonum(4.5).into()));
     }
 
+    #[test]
+    fn test_ratio() {
+        let context = Context::default();
+        let ratio = match context.eval_string("(/ -2 6)") {
+            Ok(SExp::Ratio(ratio)) => ratio,
+            o => panic!("expected a ratio, got {:?}", o),
+        };
+        assert_eq!("-1/3", format!("{:?}", ratio));
+        assert_eq!(SExp::from(Integer::from(-1)), ratio.numerator());
+        assert_eq!(SExp::from(Integer::from(3)), ratio.denominator());
+        assert_eq!(-1.0 / 3.0, f64::from(&ratio));
+        assert_eq!(context.eval_string("-1/3"), Ok(ratio.into()));
+
+        match context.eval_string("(/ 1 (expt 2 100))") {
+            Ok(SExp::Ratio(ratio)) => {
+                assert!(match ratio.denominator() {
+                    SExp::BigInt(_) => true,
+                    _ => false,
+                });
+                assert_eq!(2f64.powi(-100), f64::from(&ratio));
+            }
+            o => panic!("expected a ratio, got {:?}", o),
+        }
+
+        // Flonums are still `Rational`.
+        assert_eq!(context.eval_string("0.5"), Ok(context.flonum(0.5).into()));
+    }
+
+    #[test]
+    fn test_complex() {
+        let context = Context::default();
+        let complex = match context.eval_string("(make-rectangular 1 -2)") {
+            Ok(SExp::Complex(complex)) => complex,
+            o => panic!("expected a complex number, got {:?}", o),
+        };
+        assert_eq!("1-2i", format!("{:?}", complex));
+        assert_eq!(SExp::from(Integer::from(1)), complex.real());
+        assert_eq!(1.0, complex.re());
+        assert_eq!(-2.0, complex.im());
+    }
+
     #[test]
     fn test_string() {
         let context = Context::default();
@@ -555,8 +3193,8 @@ mod tests {
 
     #[test]
     fn test_standard_env() {
-        let mut context = Context::default();
-        context.standard_env().unwrap();
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
         context.eval_string("(import (srfi 1))").unwrap();
         assert_eq!(
             Ok(Integer::from(1).into()),
@@ -564,4 +3202,321 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_define_fn() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.define_fn("add", |a: i64, b: i64| Integer::from(a + b)).unwrap();
+        assert_eq!(Ok(SExp::from(Integer::from(3))), context.eval_string("(add 1 2)"));
+        assert!(context.eval_string("(add 1)").is_err());
+        assert!(context.eval_string("(add 1 \"two\")").is_err());
+        let add = match context.eval_string("add") {
+            Ok(SExp::Procedure(add)) => add,
+            o => panic!("expected a procedure, got {:?}", o),
+        };
+        assert_eq!(
+            Ok(SExp::from(Integer::from(5))),
+            add.call(&[Integer::from(2).into(), Integer::from(3).into()])
+        );
+    }
+
+    #[test]
+    fn test_define_fn_optional_and_rest() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context
+            .define_fn("sum", |first: i64, rest: Rest<i64>| {
+                Integer::from(first + rest.0.iter().sum::<i64>())
+            })
+            .unwrap();
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_string("(sum 1)"));
+        assert_eq!(Ok(SExp::from(Integer::from(6))), context.eval_string("(sum 1 2 3)"));
+        assert!(context.eval_string("(sum)").is_err());
+        context
+            .define_fn("given?", |c: Optional<char>| if c.0.is_some() { TRUE } else { FALSE })
+            .unwrap();
+        assert_eq!(Ok(SExp::from(FALSE)), context.eval_string("(given?)"));
+        assert_eq!(Ok(SExp::from(TRUE)), context.eval_string("(given? #\\a)"));
+        assert!(context.eval_string("(given? #\\a #\\b)").is_err());
+    }
+
+    #[test]
+    fn test_define_fn_parameter_order() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        let e = context.define_fn("f", |_: Optional<i64>, b: i64| b).unwrap_err();
+        assert_eq!(
+            "f: a required parameter follows an optional one",
+            RustString::from(&e.message())
+        );
+        assert!(context.define_fn("g", |_: Rest<i64>, b: Optional<i64>| b.0).is_err());
+        assert!(context.define_fn("h", |_: Rest<i64>, b: i64| b).is_err());
+        assert!(!context.env().contains("f"));
+    }
+
+    #[test]
+    fn test_define_fn_panic() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.define_fn("boom", || -> Void { panic!("boom") }).unwrap();
+        let e = match context.eval_string("(boom)") {
+            Err(e) => e,
+            o => panic!("expected an error, got {:?}", o),
+        };
+        assert_eq!("boom: panicked: boom", RustString::from(&e.message()));
+        assert_eq!(
+            Ok(SExp::from(Integer::from(1))),
+            context.eval_string("(guard (e (#t 1)) (boom))")
+        );
+    }
+
+    #[test]
+    fn test_define_fn_nul() {
+        let context = Context::default();
+        assert!(context.define_fn("bad\0name", || 1).is_err());
+        context.define_fn("fail", || -> Result<i64, RustString> { Err("a\0b".into()) }).unwrap();
+        let e = match context.eval_string("(fail)") {
+            Err(e) => e,
+            o => panic!("expected an error, got {:?}", o),
+        };
+        assert_eq!("a\\0b", RustString::from(&e.message()));
+    }
+
+    #[test]
+    fn test_define_plain_fn() {
+        fn repeat_len(n: i64, word: RustString) -> Vec<f64> {
+            vec![word.len() as f64; n as usize]
+        }
+        fn parse(s: RustString) -> Result<i64, std::num::ParseIntError> {
+            s.parse()
+        }
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.define_fn("repeat-len", repeat_len).unwrap();
+        context.define_fn("parse", parse).unwrap();
+        let lengths = context.eval_string("(repeat-len 2 \"abc\")").unwrap();
+        assert_eq!(Ok(vec![3.0, 3.0]), Vec::<f64>::from_sexp(lengths));
+        assert_eq!(Ok(SExp::from(Integer::from(12))), context.eval_string("(parse \"12\")"));
+        let e = match context.eval_string("(parse \"twelve\")") {
+            Err(e) => e,
+            o => panic!("expected an error, got {:?}", o),
+        };
+        assert_eq!("invalid digit found in string", RustString::from(&e.message()));
+    }
+
+    #[test]
+    fn test_from_sexp() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        let eval = |code| context.eval_string(code).unwrap();
+        assert_eq!(Ok(None), Option::<i64>::from_sexp(eval("#f")));
+        assert_eq!(Ok(Some(1)), Option::<i64>::from_sexp(eval("1")));
+        assert_eq!(Ok(vec![1, 2]), Vec::<u8>::from_sexp(eval("#(1 2)")));
+        assert!(Vec::<u8>::from_sexp(eval("'(1 256)")).is_err());
+        assert_eq!(
+            Ok((1, RustString::from("a"), true)),
+            <(i64, RustString, bool)>::from_sexp(eval("'(1 \"a\" #t)"))
+        );
+        assert!(<(i64, i64)>::from_sexp(eval("'(1 2 3)")).is_err());
+        let mut map = HashMap::new();
+        map.insert(RustString::from("a"), 1);
+        map.insert(RustString::from("b"), 2);
+        assert_eq!(Ok(map), HashMap::from_sexp(eval("'((a . 1) (b . 2))")));
+    }
+
+    #[test]
+    fn test_into_sexp() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.eval_string("(import (srfi 69))").unwrap();
+        assert_eq!(SExp::from(FALSE), None::<i64>.into_sexp(&context));
+        assert_eq!(
+            context.eval_string("'(1 \"a\" #\\b)").unwrap(),
+            (1, "a", 'b').into_sexp(&context)
+        );
+        assert_eq!(context.eval_string("'(1.5)").unwrap(), vec![1.5].into_sexp(&context));
+        match Err::<i64, _>("failed").into_sexp(&context) {
+            SExp::Exception(e) => assert_eq!("failed", RustString::from(&e.message())),
+            o => panic!("expected an error, got {:?}", o),
+        }
+        let mut map = HashMap::new();
+        map.insert("a", 1);
+        let table = match map.into_sexp(&context) {
+            SExp::HashTable(table) => table,
+            o => panic!("expected a hash table, got {:?}", o),
+        };
+        assert_eq!(
+            Ok(Some(SExp::from(Integer::from(1)))),
+            table.get(&context.string("a").into())
+        );
+    }
+
+    #[cfg(feature = "bignums")]
+    #[test]
+    fn test_into_sexp_bignum() {
+        let context = Context::default();
+        let max = u64::max_value().into_sexp(&context);
+        assert_eq!(Ok(u64::max_value()), u64::from_sexp(max));
+    }
+
+    #[test]
+    fn test_call() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.eval_string("(define (add a b) (+ a b))").unwrap();
+        assert_eq!(Ok(SExp::from(Integer::from(3))), context.call("add", (1, 2)));
+        assert_eq!(
+            Ok(context.string("ab").into()),
+            context.call("string-append", ("a", "b"))
+        );
+        assert_eq!(
+            Ok(SExp::from(Integer::from(9))),
+            context.call("string-length", ("\") (exit)",))
+        );
+        let list = context.call("list", vec![1, 2, 3]).unwrap();
+        assert_eq!(Ok(SExp::from(Integer::from(3))), context.call("length", (&list,)));
+        assert_eq!(Ok(SExp::from(NULL)), context.call("list", ()));
+        assert!(context.call("add", (1,)).is_err());
+        assert!(context.call("add", (1, "two")).is_err());
+        assert!(context.call("undefined-procedure", ()).is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.eval_string("(define answer 42)").unwrap();
+        assert_eq!(Some(SExp::from(Integer::from(42))), context.lookup("answer"));
+        match context.lookup("car") {
+            Some(SExp::Procedure(_)) => (),
+            o => panic!("expected a procedure, got {:?}", o),
+        }
+        assert!(context.lookup("undefined-variable").is_none());
+    }
+
+    #[test]
+    fn test_env_bindings() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        let env = context.env();
+        env.define("verbose", true).unwrap();
+        env.define("answer", 41).unwrap();
+        assert!(env.contains("answer"));
+        assert!(env.contains("car"));
+        assert!(env.contains("if"));
+        assert!(!env.contains("undefined-variable"));
+        assert_eq!(Some(SExp::from(TRUE)), env.get("verbose"));
+        assert!(env.get("if").is_none());
+        assert!(env.get("undefined-variable").is_none());
+        env.set("answer", 42).unwrap();
+        assert!(env.set("undefined-variable", 1).is_err());
+        assert_eq!(Ok(SExp::from(Integer::from(43))), context.eval_string("(+ answer 1)"));
+        context.eval_string("(define result (if verbose 'loud 'quiet))").unwrap();
+        assert_eq!(Some(context.intern("loud").into()), env.get("result"));
+        let bindings = env.bindings().take(3).map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(vec!["result", "answer", "verbose"], bindings);
+        assert!(format!("{:?}", env).starts_with("#<env {\"result\": loud, \"answer\": 42"));
+    }
+
+    #[test]
+    fn test_eval_in() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        let global = context.env();
+        global.define("shared", 1).unwrap();
+        let first = global.child().unwrap();
+        let second = global.child().unwrap();
+        context.eval_in(&first, "(define x 'first)").unwrap();
+        context.eval_in(&second, "(define x 'second)").unwrap();
+        context.eval_in(&second, "(define shared 2)").unwrap();
+        assert_eq!(Ok(context.intern("first").into()), context.eval_in(&first, "x"));
+        assert_eq!(Ok(context.intern("second").into()), context.eval_in(&second, "x"));
+        assert!(context.eval_string("x").is_err());
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_in(&first, "shared"));
+        assert_eq!(Ok(SExp::from(Integer::from(2))), context.eval_in(&second, "shared"));
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_string("shared"));
+        assert_eq!(Ok(SExp::from(Integer::from(3))), context.eval_in(&first, "(+ shared 2)"));
+        let bindings = second.bindings().map(|(name, _)| name).collect::<Vec<_>>();
+        assert_eq!(vec!["shared", "x"], bindings);
+    }
+
+    #[test]
+    fn test_standard_envs_are_separate() {
+        let context = Context::default();
+        let first = context.standard_env(7).unwrap();
+        let second = context.standard_env(7).unwrap();
+        context.eval_in(&first, "(define (car x) 'shadowed)").unwrap();
+        assert_eq!(Ok(context.intern("shadowed").into()), context.eval_in(&first, "(car '(1))"));
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_in(&second, "(car '(1))"));
+        assert!(context.eval_string("(when #t 1)").is_err());
+        context.set_env(&second);
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_string("(when #t 1)"));
+    }
+
+    #[test]
+    fn test_null_and_primitive_env() {
+        let context = Context::default();
+        let null = context.null_env(7).unwrap();
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_in(&null, "(if #t 1 2)"));
+        assert!(context.eval_in(&null, "(car '(1 2))").is_err());
+        let primitive = context.primitive_env(7).unwrap();
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_in(&primitive, "(car '(1 2))"));
+        assert!(context.eval_in(&primitive, "(when #t 1)").is_err());
+    }
+
+    #[test]
+    fn test_env_outlives_other_wrappers() {
+        let context = Context::default();
+        let standard = context.standard_env(7).unwrap();
+        let null = context.null_env(7).unwrap();
+        context.set_env(&standard);
+        // Wraps the standard environment again and drops the wrapper.
+        context.lookup("car");
+        context.set_env(&null);
+        unsafe { sexp_gc(context.0, ptr::null_mut()) };
+        assert_eq!(Ok(SExp::from(Integer::from(1))), context.eval_in(&standard, "(car '(1 2))"));
+    }
+
+    #[test]
+    fn test_output_port() {
+        // Shares what is written with the test, as the port owns its writer.
+        struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+        impl std::io::Write for SharedBuffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.borrow_mut().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        let buffer = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut port = context.output_port(SharedBuffer(buffer.clone())).unwrap();
+        assert!(port.is_output());
+        assert!(!port.is_input());
+        context.set_current_output_port(&port);
+        context.eval_string("(begin (display \"hello\") (newline))").unwrap();
+        port.flush();
+        assert_eq!(b"hello\n", &buffer.borrow()[..]);
+        port.close();
+        assert!(!port.is_open());
+        assert_eq!(1, std::rc::Rc::strong_count(&buffer));
+    }
+
+    #[test]
+    fn test_input_port() {
+        let context = Context::default();
+        context.set_env(&context.standard_env(7).unwrap());
+        context.eval_string("(import (scheme base))").unwrap();
+        let port = context.input_port(std::io::Cursor::new("first\nsecond\n")).unwrap();
+        assert!(port.is_input());
+        context.set_current_input_port(&port);
+        assert_eq!(Ok(context.string("first").into()), context.eval_string("(read-line)"));
+        assert_eq!(Ok(context.string("second").into()), context.eval_string("(read-line)"));
+    }
+
 }